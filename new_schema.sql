@@ -3,6 +3,9 @@ CREATE TABLE known_entities (
     target_type TEXT NOT NULL,
     PRIMARY KEY (target_id, target_type),
 
+    -- IANA timezone of the entity used by weekend vote rules, NULL to use the timezone of the rule
+    timezone TEXT,
+
     -- If target_type is 'bot', _bot_fk becomes the ID. If not, it's NULL.
     _bot_fk TEXT GENERATED ALWAYS AS (
         CASE WHEN target_type = 'bot' THEN target_id ELSE NULL END
@@ -13,6 +16,11 @@ CREATE TABLE known_entities (
         CASE WHEN target_type = 'user' THEN target_id ELSE NULL END
     ) STORED,
 
+    -- Same with server
+    _server_fk TEXT GENERATED ALWAYS AS (
+        CASE WHEN target_type = 'server' THEN target_id ELSE NULL END
+    ) STORED,
+
     -- Ensure referential integrity
     CONSTRAINT fk_known_bots 
         FOREIGN KEY (_bot_fk) REFERENCES bots(bot_id) 
@@ -20,7 +28,13 @@ CREATE TABLE known_entities (
 
     CONSTRAINT fk_known_users 
         FOREIGN KEY (_user_fk) REFERENCES users(user_id) 
+        ON DELETE CASCADE,
+
+    CONSTRAINT fk_known_servers 
+        FOREIGN KEY (_server_fk) REFERENCES servers(server_id) 
         ON DELETE CASCADE
+
+    -- Team IDs are UUIDs so teams cannot have a FK here, EntityManager::delete removes them instead
 );
 
 CREATE TABLE test_sessions (
@@ -1,6 +1,7 @@
-use std::{fs::File, sync::LazyLock};
+use std::{collections::HashMap, fs::File, sync::LazyLock};
 use serde::{Deserialize, Serialize};
 use crate::Error;
+use crate::entity::multipliers::VoteRules;
 
 /// Global config object
 pub static CONFIG: LazyLock<Config> = LazyLock::new(|| Config::load().expect("Failed to load config"));
@@ -11,8 +12,42 @@ pub struct Config {
     pub cdn_url: String,
     /// Path on disk for CDN storage
     pub cdn_path: String,
+    /// Base URL for the frontend (used when building entity URLs)
+    ///
+    /// Defaults to an empty string, making entity URLs relative (e.g. /bot/<id>)
+    #[serde(default)]
+    pub frontend_url: String,
     /// Proxy URL for outgoing requests
     pub proxy_url: String,
+    /// Settings for the in-process entity cache
+    #[serde(default)]
+    pub entity_cache: EntityCacheConfig,
+    /// Vote rules per target type, target types without rules use VoteRules::default
+    ///
+    /// Rules changed by staff at runtime are stored in entity_vote_rules and take priority over these
+    #[serde(default)]
+    pub vote_rules: HashMap<String, VoteRules>,
+    /// Dev mode: load layer Luau code from this directory instead of the copy embedded at build time,
+    /// reloading layers when it changes
+    #[serde(default)]
+    pub luau_dev_dir: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct EntityCacheConfig {
+    /// How long (in seconds) a cached entity stays valid for
+    pub ttl_secs: u64,
+    /// The maximum amount of cached entries across all entity types and views
+    pub max_entries: usize,
+}
+
+impl Default for EntityCacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl_secs: 60,
+            max_entries: 10_000,
+        }
+    }
 }
 
 impl Config {
@@ -0,0 +1,338 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::CONFIG;
+
+/// Process wide entity cache, shared by every LayerThread
+pub static ENTITY_CACHE: LazyLock<EntityCache> = LazyLock::new(|| {
+    EntityCache::new(
+        Duration::from_secs(CONFIG.entity_cache.ttl_secs),
+        CONFIG.entity_cache.max_entries,
+    )
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// The object type stored in a cache entry
+pub enum CacheView {
+    Public,
+    Summary,
+}
+
+struct CacheEntry {
+    value: serde_json::Value,
+    inserted_at: Instant,
+}
+
+type EntityKey = (String, String);
+type EntryKey = (CacheView, &'static str);
+
+/// The entries of a single entity
+///
+/// The type name of the object is part of the key as the same entity can be cached both as its own
+/// object and as the tagged EntityEnum object depending on which manager fetched it
+type EntitySlot = HashMap<EntryKey, CacheEntry>;
+
+#[derive(Default)]
+struct CacheState {
+    entities: HashMap<EntityKey, EntitySlot>,
+    /// The amount of entries across all slots
+    len: usize,
+    /// Entries in insertion order, used for eviction
+    ///
+    /// Replaced and invalidated entries are left in the queue and skipped when popped, the queue is
+    /// compacted once it grows past twice the entry limit
+    order: VecDeque<(EntityKey, EntryKey, Instant)>,
+    /// Bumped by every invalidation
+    generation: u64,
+    /// The generation at which each entity was last invalidated
+    invalidated_at: HashMap<EntityKey, u64>,
+    /// Generations below this may have had their invalidations pruned from invalidated_at
+    generation_floor: u64,
+}
+
+impl CacheState {
+    /// Returns true if the entry in the queue is still the one stored in its slot
+    fn is_live(&self, key: &EntityKey, entry_key: &EntryKey, inserted_at: Instant) -> bool {
+        self.entities
+            .get(key)
+            .and_then(|slot| slot.get(entry_key))
+            .is_some_and(|entry| entry.inserted_at == inserted_at)
+    }
+
+    /// Removes an entry, returning it if it was present
+    fn remove(&mut self, key: &EntityKey, entry_key: &EntryKey) -> Option<CacheEntry> {
+        let slot = self.entities.get_mut(key)?;
+        let entry = slot.remove(entry_key)?;
+        if slot.is_empty() {
+            self.entities.remove(key);
+        }
+        self.len -= 1;
+        Some(entry)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, utoipa::ToSchema)]
+/// Counters of the entity cache since startup
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Entries dropped to stay under the entry limit
+    pub evictions: u64,
+    /// Entities invalidated due to a change
+    pub invalidations: u64,
+    /// The amount of entries currently cached
+    pub entries: usize,
+}
+
+/// A TTL cache of serialized entity objects keyed by (target_type, id, view)
+///
+/// Objects are stored as JSON so any entity type can share one cache, the lock is never held across an await
+pub struct EntityCache {
+    ttl: Duration,
+    max_entries: usize,
+    state: Mutex<CacheState>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+impl EntityCache {
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            max_entries,
+            state: Mutex::new(CacheState::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            invalidations: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the cached object if present and not expired
+    pub fn get<T: for<'de> Deserialize<'de>>(&self, target_type: &str, id: &str, view: CacheView) -> Option<T> {
+        let value = {
+            let state = self.state.lock().expect("entity cache poisoned");
+            state
+                .entities
+                .get(&(target_type.to_string(), id.to_string()))
+                .and_then(|slot| slot.get(&(view, std::any::type_name::<T>())))
+                .filter(|entry| entry.inserted_at.elapsed() < self.ttl)
+                .map(|entry| entry.value.clone())
+        };
+
+        // A value that no longer deserializes is treated as a miss and gets overwritten
+        match value.and_then(|v| serde_json::from_value(v).ok()) {
+            Some(obj) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(obj)
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Returns the current invalidation generation
+    ///
+    /// This must be read before fetching the object that is later passed to insert, so that an
+    /// invalidation racing the fetch stops the (possibly stale) object from being cached
+    pub fn generation(&self) -> u64 {
+        self.state.lock().expect("entity cache poisoned").generation
+    }
+
+    /// Caches an object fetched at the given generation, evicting the oldest entries if the cache is full
+    ///
+    /// The object is not cached if the entity was invalidated after the generation was read
+    pub fn insert<T: Serialize>(&self, target_type: &str, id: &str, view: CacheView, generation: u64, obj: &T) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        let Ok(value) = serde_json::to_value(obj) else {
+            return;
+        };
+
+        let mut state = self.state.lock().expect("entity cache poisoned");
+
+        let key = (target_type.to_string(), id.to_string());
+
+        if generation < state.generation_floor || state.invalidated_at.get(&key).is_some_and(|g| *g > generation) {
+            return;
+        }
+
+        let entry_key = (view, std::any::type_name::<T>());
+
+        if state.remove(&key, &entry_key).is_none() {
+            self.make_room(&mut state);
+        }
+
+        let inserted_at = Instant::now();
+        state.entities.entry(key.clone()).or_default().insert(entry_key, CacheEntry { value, inserted_at });
+        state.len += 1;
+        state.order.push_back((key, entry_key, inserted_at));
+
+        if state.order.len() > self.max_entries.saturating_mul(2) {
+            let order = std::mem::take(&mut state.order);
+            let live = order
+                .into_iter()
+                .filter(|(key, entry_key, inserted_at)| state.is_live(key, entry_key, *inserted_at))
+                .collect();
+            state.order = live;
+        }
+    }
+
+    /// Drops every cached object of an entity
+    pub fn invalidate(&self, target_type: &str, id: &str) {
+        let mut state = self.state.lock().expect("entity cache poisoned");
+        let key = (target_type.to_string(), id.to_string());
+
+        state.generation += 1;
+        let generation = state.generation;
+        state.invalidated_at.insert(key.clone(), generation);
+
+        // Only in-flight fetches need the per entity generations, so forget them all once there are too many
+        if state.invalidated_at.len() > self.max_entries.max(1024) {
+            state.invalidated_at.clear();
+            state.generation_floor = generation;
+        }
+
+        if let Some(slot) = state.entities.remove(&key) {
+            state.len -= slot.len();
+            self.invalidations.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Drops every cached object
+    pub fn clear(&self) {
+        let mut state = self.state.lock().expect("entity cache poisoned");
+        state.entities.clear();
+        state.order.clear();
+        state.len = 0;
+        state.generation += 1;
+        state.invalidated_at.clear();
+        state.generation_floor = state.generation;
+    }
+
+    /// Returns the counters of the cache
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.state.lock().expect("entity cache poisoned").len;
+
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            invalidations: self.invalidations.load(Ordering::Relaxed),
+            entries,
+        }
+    }
+
+    /// Ensures there is room for one more entry by dropping the oldest ones
+    ///
+    /// As every entry has the same TTL, the oldest entries are also the first to expire so expired
+    /// entries are dropped before live ones. Only live entries count as evictions
+    fn make_room(&self, state: &mut CacheState) {
+        while state.len >= self.max_entries {
+            let Some((key, entry_key, inserted_at)) = state.order.pop_front() else {
+                break;
+            };
+
+            if !state.is_live(&key, &entry_key, inserted_at) {
+                continue;
+            }
+
+            state.remove(&key, &entry_key);
+
+            if inserted_at.elapsed() < self.ttl {
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entity_cache() {
+        let cache = EntityCache::new(Duration::from_secs(60), 2);
+
+        assert_eq!(cache.get::<String>("bot", "1", CacheView::Public), None);
+        cache.insert("bot", "1", CacheView::Public, 0, &"one".to_string());
+        assert_eq!(cache.get::<String>("bot", "1", CacheView::Public), Some("one".to_string()));
+
+        // Views and object types are cached separately
+        assert_eq!(cache.get::<String>("bot", "1", CacheView::Summary), None);
+        assert_eq!(cache.get::<u32>("bot", "1", CacheView::Public), None);
+
+        // Inserting past the limit evicts the oldest entry
+        std::thread::sleep(Duration::from_millis(1));
+        cache.insert("bot", "2", CacheView::Public, 0, &"two".to_string());
+        std::thread::sleep(Duration::from_millis(1));
+        cache.insert("bot", "3", CacheView::Public, 0, &"three".to_string());
+        assert_eq!(cache.get::<String>("bot", "1", CacheView::Public), None);
+        assert_eq!(cache.get::<String>("bot", "3", CacheView::Public), Some("three".to_string()));
+
+        cache.invalidate("bot", "3");
+        assert_eq!(cache.get::<String>("bot", "3", CacheView::Public), None);
+
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 2);
+        assert_eq!(stats.misses, 5);
+        assert_eq!(stats.evictions, 1);
+        assert_eq!(stats.invalidations, 1);
+        assert_eq!(stats.entries, 1);
+    }
+
+    #[test]
+    fn test_entity_cache_ttl() {
+        let cache = EntityCache::new(Duration::ZERO, 10);
+        cache.insert("bot", "1", CacheView::Summary, 0, &1u32);
+        assert_eq!(cache.get::<u32>("bot", "1", CacheView::Summary), None);
+    }
+
+    #[test]
+    fn test_entity_cache_generation() {
+        let cache = EntityCache::new(Duration::from_secs(60), 10);
+
+        // A fetch that started before an invalidation must not be cached
+        let generation = cache.generation();
+        cache.invalidate("bot", "1");
+        cache.insert("bot", "1", CacheView::Public, generation, &1u32);
+        assert_eq!(cache.get::<u32>("bot", "1", CacheView::Public), None);
+
+        // Other entities are unaffected
+        cache.insert("bot", "2", CacheView::Public, generation, &2u32);
+        assert_eq!(cache.get::<u32>("bot", "2", CacheView::Public), Some(2));
+
+        // A fetch that started after the invalidation is cached
+        let generation = cache.generation();
+        cache.insert("bot", "1", CacheView::Public, generation, &1u32);
+        assert_eq!(cache.get::<u32>("bot", "1", CacheView::Public), Some(1));
+    }
+
+    #[test]
+    fn test_entity_cache_replace() {
+        let cache = EntityCache::new(Duration::from_secs(60), 2);
+
+        // Replacing an entry does not count towards the limit or evict anything
+        for i in 0..10u32 {
+            cache.insert("bot", "1", CacheView::Public, 0, &i);
+        }
+        cache.insert("bot", "2", CacheView::Public, 0, &2u32);
+
+        assert_eq!(cache.get::<u32>("bot", "1", CacheView::Public), Some(9));
+        assert_eq!(cache.get::<u32>("bot", "2", CacheView::Public), Some(2));
+
+        let stats = cache.stats();
+        assert_eq!(stats.evictions, 0);
+        assert_eq!(stats.entries, 2);
+    }
+}
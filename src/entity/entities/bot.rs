@@ -0,0 +1,479 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config::CONFIG;
+use crate::entity::multipliers::compute_vote_info;
+use crate::entity::list::{EntityList, EntityListQuery, ListKeys, cursor_cols, fetch_page};
+use crate::entity::{Entity, EntityFlags, EntityInfo, EntityVoteInfo};
+use crate::utils::asset::{AssetType, asset_metadata, resolve_asset_metadata_to_url};
+
+/// Joins shared by all bot object queries
+///
+/// Name comes from the discord user cache, votes from entity_approx_votes
+const BOT_JOINS: &str = "FROM bots b
+LEFT JOIN internal_user_cache__discord c ON c.id = b.bot_id
+LEFT JOIN vanity v ON v.itag = b.vanity_ref
+LEFT JOIN entity_approx_votes a ON a.target_id = b.bot_id AND a.target_type = 'bot'";
+
+const BOT_FULL_COLS: &str = "b.bot_id, b.client_id, COALESCE(c.display_name, b.bot_id) AS name, v.code::TEXT AS vanity,
+b.owner, b.team_owner, b.short, b.long, b.prefix, b.library, b.invite, b.tags, b.extra_links, b.nsfw, b.premium,
+b.start_premium_period, EXTRACT(EPOCH FROM b.premium_period_length)::BIGINT AS premium_period_length,
+b.servers, b.shards, b.shard_list, b.users, b.clicks, b.invite_clicks, b.type, b.vote_banned, b.cert_reason,
+b.uptime, b.total_uptime, b.claimed_by, b.last_claimed, b.approval_note, b.captcha_opt_out,
+b.cache_server_uninvitable, b.last_stats_post, b.created_at, b.updated_at,
+COALESCE(a.approximate_votes, 0) AS approximate_votes";
+
+const BOT_PUBLIC_COLS: &str = "b.bot_id, b.client_id, COALESCE(c.display_name, b.bot_id) AS name, v.code::TEXT AS vanity,
+b.owner, b.team_owner, b.short, b.long, b.prefix, b.library, b.invite, b.tags, b.extra_links, b.nsfw, b.premium,
+b.servers, b.shards, b.users, b.clicks, b.invite_clicks, b.type, b.vote_banned, b.cert_reason,
+b.uptime, b.total_uptime, b.last_stats_post, b.created_at, b.updated_at,
+COALESCE(a.approximate_votes, 0) AS approximate_votes";
+
+const BOT_SUMMARY_COLS: &str = "b.bot_id, b.client_id, COALESCE(c.display_name, b.bot_id) AS name, v.code::TEXT AS vanity,
+b.short, b.tags, b.nsfw, b.premium, b.servers, b.type,
+COALESCE(a.approximate_votes, 0) AS approximate_votes";
+
+/// Expressions used when listing bots
+const BOT_LIST_KEYS: ListKeys<'static> = ListKeys {
+    id: "b.bot_id",
+    votes: "COALESCE(a.approximate_votes, 0)",
+    created: "b.created_at",
+    name: "COALESCE(c.display_name, b.bot_id)",
+    search: &["COALESCE(c.display_name, b.bot_id)", "b.short"],
+};
+
+/// The full bot object. Internal use only, never return this in public API responses
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct BotFull {
+    pub bot_id: String,
+    pub client_id: String,
+    pub name: String,
+    pub vanity: Option<String>,
+    pub owner: Option<String>,
+    pub team_owner: Option<uuid::Uuid>,
+    pub short: String,
+    pub long: String,
+    pub prefix: String,
+    pub library: String,
+    pub invite: String,
+    pub tags: Vec<String>,
+    #[schema(value_type = Object)]
+    pub extra_links: serde_json::Value,
+    pub nsfw: bool,
+    pub premium: bool,
+    pub start_premium_period: DateTime<Utc>,
+    /// Length of the premium period in seconds
+    pub premium_period_length: i64,
+    pub servers: i32,
+    pub shards: i32,
+    pub shard_list: Vec<i64>,
+    pub users: i32,
+    pub clicks: i32,
+    pub invite_clicks: i32,
+    /// The state of the bot (approved, pending, certified, banned etc.)
+    #[sqlx(rename = "type")]
+    pub state: String,
+    pub vote_banned: bool,
+    pub cert_reason: Option<String>,
+    pub uptime: i64,
+    pub total_uptime: i64,
+    pub claimed_by: Option<String>,
+    pub last_claimed: Option<DateTime<Utc>>,
+    pub approval_note: String,
+    pub captcha_opt_out: bool,
+    pub cache_server_uninvitable: Option<String>,
+    pub last_stats_post: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub approximate_votes: i32,
+}
+
+/// The public bot object, used in API responses
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct BotPublic {
+    pub bot_id: String,
+    pub client_id: String,
+    pub name: String,
+    pub vanity: Option<String>,
+    pub owner: Option<String>,
+    pub team_owner: Option<uuid::Uuid>,
+    pub short: String,
+    pub long: String,
+    pub prefix: String,
+    pub library: String,
+    pub invite: String,
+    pub tags: Vec<String>,
+    #[schema(value_type = Object)]
+    pub extra_links: serde_json::Value,
+    pub nsfw: bool,
+    pub premium: bool,
+    pub servers: i32,
+    pub shards: i32,
+    pub users: i32,
+    pub clicks: i32,
+    pub invite_clicks: i32,
+    /// The state of the bot (approved, pending, certified, banned etc.)
+    #[sqlx(rename = "type")]
+    pub state: String,
+    pub vote_banned: bool,
+    pub cert_reason: Option<String>,
+    pub uptime: i64,
+    pub total_uptime: i64,
+    pub last_stats_post: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub approximate_votes: i32,
+}
+
+/// The summary (short form) bot object, used in listings
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct BotSummary {
+    pub bot_id: String,
+    pub client_id: String,
+    pub name: String,
+    pub vanity: Option<String>,
+    pub short: String,
+    pub tags: Vec<String>,
+    pub nsfw: bool,
+    pub premium: bool,
+    pub servers: i32,
+    /// The state of the bot (approved, pending, certified, banned etc.)
+    #[sqlx(rename = "type")]
+    pub state: String,
+    pub approximate_votes: i32,
+}
+
+/// Data needed to add a new bot
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct BotCreate {
+    pub bot_id: String,
+    pub client_id: String,
+    /// The user owning the bot. Exactly one of owner and team_owner must be set
+    pub owner: Option<String>,
+    /// The team owning the bot. Exactly one of owner and team_owner must be set
+    pub team_owner: Option<uuid::Uuid>,
+    pub short: String,
+    pub long: String,
+    pub prefix: String,
+    pub library: String,
+    pub invite: String,
+    pub tags: Vec<String>,
+    #[schema(value_type = Object)]
+    pub extra_links: serde_json::Value,
+    pub nsfw: bool,
+}
+
+/// Partial update of a bot, None fields are left unchanged
+#[derive(Debug, Clone, Default, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct BotUpdate {
+    pub short: Option<String>,
+    pub long: Option<String>,
+    pub prefix: Option<String>,
+    pub library: Option<String>,
+    pub invite: Option<String>,
+    pub tags: Option<Vec<String>>,
+    #[schema(value_type = Option<Object>)]
+    pub extra_links: Option<serde_json::Value>,
+    pub nsfw: Option<bool>,
+}
+
+/// Filters for listing bots
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BotListFilter {
+    /// Only return bots in this state (approved, certified etc.)
+    pub state: Option<String>,
+    pub nsfw: Option<bool>,
+    pub premium: Option<bool>,
+    /// Only return bots with all of these tags
+    pub tags: Option<Vec<String>>,
+    /// Only return bots owned by this user
+    pub owner: Option<String>,
+    /// Only return bots owned by this team
+    pub team_owner: Option<uuid::Uuid>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Bot {
+    pool: sqlx::PgPool,
+    diesel: crate::Db,
+}
+
+impl Bot {
+    /// Creates a new instance of the Bot entity.
+    pub fn new(pool: sqlx::PgPool, diesel: crate::Db) -> Self {
+        Self { pool, diesel }
+    }
+}
+
+impl Entity for Bot {
+    type FullObject = BotFull;
+    type PublicObject = BotPublic;
+    type SummaryObject = BotSummary;
+    type CreateObject = BotCreate;
+    type UpdateObject = BotUpdate;
+    type ListFilter = BotListFilter;
+
+    fn pool(&self) -> &sqlx::PgPool {
+        &self.pool
+    }
+
+    fn diesel(&self) -> &crate::Db {
+        &self.diesel
+    }
+
+    fn name(&self) -> &'static str {
+        "Bot"
+    }
+
+    fn target_type(&self) -> &'static str {
+        "bot"
+    }
+
+    fn cdn_folder(&self) -> &'static str {
+        "bots"
+    }
+
+    async fn flags(&self, id: &str) -> Result<EntityFlags, crate::Error> {
+        #[derive(sqlx::FromRow)]
+        struct BotFlagsRow {
+            #[sqlx(rename = "type")]
+            state: String,
+            vote_banned: bool,
+        }
+
+        let row: Option<BotFlagsRow> = sqlx::query_as("SELECT type, vote_banned FROM bots WHERE bot_id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        let Some(row) = row else {
+            return Ok(EntityFlags::NONE);
+        };
+
+        let mut flags = EntityFlags::SUPPORTS_WEBHOOKS
+            | EntityFlags::SUPPORTS_MULTIPLE_VOTES
+            | EntityFlags::SUPPORTS_UPVOTES
+            | EntityFlags::SUPPORTS_VOTE_CREDITS;
+
+        if !row.vote_banned {
+            flags |= EntityFlags::SUPPORTS_VOTING;
+        }
+
+        if row.state == "banned" {
+            flags |= EntityFlags::BANNED;
+        }
+
+        Ok(flags)
+    }
+
+    async fn get_info(&self, id: &str) -> Result<Option<EntityInfo>, crate::Error> {
+        #[derive(sqlx::FromRow)]
+        struct BotInfoRow {
+            name: String,
+            vanity: Option<String>,
+        }
+
+        let row: Option<BotInfoRow> = sqlx::query_as(&format!(
+            "SELECT COALESCE(c.display_name, b.bot_id) AS name, v.code::TEXT AS vanity {BOT_JOINS} WHERE b.bot_id = $1"
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let slug = row.vanity.as_deref().unwrap_or(id);
+
+        Ok(Some(EntityInfo {
+            name: row.name,
+            url: format!("{}/bot/{}", CONFIG.frontend_url, slug),
+            vote_url: Some(format!("{}/bot/{}/vote", CONFIG.frontend_url, slug)),
+            avatar: Some(resolve_asset_metadata_to_url(&asset_metadata(self, id, AssetType::Avatar))),
+        }))
+    }
+
+    async fn get_vote_info(&self, id: &str, user_id: Option<&str>) -> Result<EntityVoteInfo, crate::Error> {
+        let premium: Option<bool> = sqlx::query_scalar("SELECT premium FROM bots WHERE bot_id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        let Some(premium) = premium else {
+            return Err("Bot not found".into());
+        };
+
+        compute_vote_info(&self.pool, self.target_type(), id, premium, user_id).await
+    }
+
+    async fn get_full(&self, id: &str) -> Result<Self::FullObject, crate::Error> {
+        let bot: Option<BotFull> = sqlx::query_as(&format!("SELECT {BOT_FULL_COLS} {BOT_JOINS} WHERE b.bot_id = $1"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        bot.ok_or_else(|| "Bot not found".into())
+    }
+
+    async fn get_public(&self, id: &str) -> Result<Self::PublicObject, crate::Error> {
+        let bot: Option<BotPublic> = sqlx::query_as(&format!("SELECT {BOT_PUBLIC_COLS} {BOT_JOINS} WHERE b.bot_id = $1"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        bot.ok_or_else(|| "Bot not found".into())
+    }
+
+    async fn get_summary(&self, id: &str) -> Result<Self::SummaryObject, crate::Error> {
+        let bot: Option<BotSummary> = sqlx::query_as(&format!("SELECT {BOT_SUMMARY_COLS} {BOT_JOINS} WHERE b.bot_id = $1"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        bot.ok_or_else(|| "Bot not found".into())
+    }
+
+    async fn create(&self, conn: &mut sqlx::PgConnection, obj: Self::CreateObject) -> Result<String, crate::Error> {
+        if obj.owner.is_some() == obj.team_owner.is_some() {
+            return Err("Exactly one of owner or team_owner must be set".into());
+        }
+
+        let exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM bots WHERE bot_id = $1)")
+            .bind(&obj.bot_id)
+            .fetch_one(&mut *conn)
+            .await?;
+
+        if exists {
+            return Err("Bot already exists".into());
+        }
+
+        // Every bot needs a vanity, default to the bot ID
+        let vanity_ref: uuid::Uuid = sqlx::query_scalar(
+            "INSERT INTO vanity (target_id, target_type, code) VALUES ($1, 'bot', $1) RETURNING itag",
+        )
+        .bind(&obj.bot_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        sqlx::query(
+            "INSERT INTO bots (bot_id, client_id, owner, team_owner, short, long, prefix, library, invite, tags, extra_links, nsfw, vanity_ref)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)",
+        )
+        .bind(&obj.bot_id)
+        .bind(&obj.client_id)
+        .bind(&obj.owner)
+        .bind(obj.team_owner)
+        .bind(&obj.short)
+        .bind(&obj.long)
+        .bind(&obj.prefix)
+        .bind(&obj.library)
+        .bind(&obj.invite)
+        .bind(&obj.tags)
+        .bind(&obj.extra_links)
+        .bind(obj.nsfw)
+        .bind(vanity_ref)
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(obj.bot_id)
+    }
+
+    async fn resolve_id(&self, id: &str) -> Result<Option<String>, crate::Error> {
+        // IDs take priority over vanity codes, which default to the ID anyways
+        let bot_id: Option<String> = sqlx::query_scalar(
+            "SELECT bot_id FROM bots WHERE bot_id = $1
+            UNION ALL SELECT target_id FROM vanity WHERE code = $1 AND target_type = 'bot'
+            LIMIT 1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(bot_id)
+    }
+
+    async fn update(&self, id: &str, obj: Self::UpdateObject) -> Result<(), crate::Error> {
+        let mut qb = sqlx::QueryBuilder::new("UPDATE bots SET updated_at = NOW()");
+        if let Some(short) = obj.short {
+            qb.push(", short = ").push_bind(short);
+        }
+        if let Some(long) = obj.long {
+            qb.push(", long = ").push_bind(long);
+        }
+        if let Some(prefix) = obj.prefix {
+            qb.push(", prefix = ").push_bind(prefix);
+        }
+        if let Some(library) = obj.library {
+            qb.push(", library = ").push_bind(library);
+        }
+        if let Some(invite) = obj.invite {
+            qb.push(", invite = ").push_bind(invite);
+        }
+        if let Some(tags) = obj.tags {
+            qb.push(", tags = ").push_bind(tags);
+        }
+        if let Some(extra_links) = obj.extra_links {
+            qb.push(", extra_links = ").push_bind(extra_links);
+        }
+        if let Some(nsfw) = obj.nsfw {
+            qb.push(", nsfw = ").push_bind(nsfw);
+        }
+        qb.push(" WHERE bot_id = ").push_bind(id.to_string());
+
+        let res = qb.build().execute(&self.pool).await?;
+
+        if res.rows_affected() == 0 {
+            return Err("Bot not found".into());
+        }
+
+        Ok(())
+    }
+
+    async fn delete(&self, conn: &mut sqlx::PgConnection, id: &str) -> Result<(), crate::Error> {
+        let res = sqlx::query("DELETE FROM bots WHERE bot_id = $1")
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+
+        if res.rows_affected() == 0 {
+            return Err("Bot not found".into());
+        }
+
+        sqlx::query("DELETE FROM vanity WHERE target_id = $1 AND target_type = 'bot'")
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn list(&self, query: EntityListQuery<Self::ListFilter>) -> Result<EntityList<Self::SummaryObject>, crate::Error> {
+        let mut qb = sqlx::QueryBuilder::new(format!(
+            "SELECT {BOT_SUMMARY_COLS}, {} {BOT_JOINS} WHERE TRUE",
+            cursor_cols(query.sort, &BOT_LIST_KEYS)
+        ));
+
+        let filter = &query.filter;
+        if let Some(state) = &filter.state {
+            qb.push(" AND b.type = ").push_bind(state.clone());
+        }
+        if let Some(nsfw) = filter.nsfw {
+            qb.push(" AND b.nsfw = ").push_bind(nsfw);
+        }
+        if let Some(premium) = filter.premium {
+            qb.push(" AND b.premium = ").push_bind(premium);
+        }
+        if let Some(tags) = &filter.tags {
+            qb.push(" AND b.tags @> ").push_bind(tags.clone());
+        }
+        if let Some(owner) = &filter.owner {
+            qb.push(" AND b.owner = ").push_bind(owner.clone());
+        }
+        if let Some(team_owner) = filter.team_owner {
+            qb.push(" AND b.team_owner = ").push_bind(team_owner);
+        }
+
+        fetch_page(qb, &query, &BOT_LIST_KEYS, &self.pool).await
+    }
+}
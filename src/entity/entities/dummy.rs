@@ -21,6 +21,8 @@ impl Entity for Dummy {
     type PublicObject = DummyObj;
     type SummaryObject = DummyObj;
     type CreateObject = DummyObj;
+    type UpdateObject = DummyObj;
+    type ListFilter = ();
 
     fn pool(&self) -> &sqlx::PgPool {
         &self.pool
@@ -62,7 +64,7 @@ impl Entity for Dummy {
         Ok(DummyObj {})
     }
 
-    async fn create(&self, _obj: Self::CreateObject) -> Result<String, crate::Error> {
+    async fn create(&self, _conn: &mut sqlx::PgConnection, _obj: Self::CreateObject) -> Result<String, crate::Error> {
         Ok("dummy_id".to_string())
     }
 }
\ No newline at end of file
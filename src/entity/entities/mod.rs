@@ -0,0 +1,11 @@
+mod dummy;
+mod bot;
+mod user;
+mod server;
+mod team;
+
+pub use dummy::{Dummy, DummyObj};
+pub use bot::{Bot, BotCreate, BotFull, BotListFilter, BotPublic, BotSummary, BotUpdate};
+pub use user::{User, UserCreate, UserFull, UserListFilter, UserPublic, UserSummary, UserUpdate};
+pub use server::{Server, ServerCreate, ServerFull, ServerListFilter, ServerPublic, ServerSummary, ServerUpdate};
+pub use team::{Team, TeamCreate, TeamFull, TeamListFilter, TeamPublic, TeamSummary, TeamUpdate};
@@ -0,0 +1,454 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config::CONFIG;
+use crate::entity::multipliers::compute_vote_info;
+use crate::entity::list::{EntityList, EntityListQuery, ListKeys, cursor_cols, fetch_page};
+use crate::entity::{Entity, EntityFlags, EntityInfo, EntityVoteInfo};
+use crate::utils::asset::{AssetType, asset_metadata, resolve_asset_metadata_to_url};
+
+/// Joins shared by all server object queries
+const SERVER_JOINS: &str = "FROM servers s
+LEFT JOIN vanity v ON v.itag = s.vanity_ref
+LEFT JOIN entity_approx_votes a ON a.target_id = s.server_id AND a.target_type = 'server'";
+
+const SERVER_FULL_COLS: &str = "s.server_id, s.name, v.code::TEXT AS vanity, s.team_owner, s.short, s.long, s.invite,
+s.tags, s.extra_links, s.state, s.type, s.nsfw, s.premium, s.start_premium_period,
+EXTRACT(EPOCH FROM s.premium_period_length)::BIGINT AS premium_period_length,
+s.total_members, s.online_members, s.clicks, s.invite_clicks, s.vote_banned, s.captcha_opt_out,
+s.login_required_for_invite, s.blacklisted_users, s.claimed_by, s.last_claimed, s.created_at,
+COALESCE(a.approximate_votes, 0) AS approximate_votes";
+
+const SERVER_PUBLIC_COLS: &str = "s.server_id, s.name, v.code::TEXT AS vanity, s.team_owner, s.short, s.long,
+s.tags, s.extra_links, s.state, s.type, s.nsfw, s.premium, s.total_members, s.online_members,
+s.clicks, s.invite_clicks, s.vote_banned, s.login_required_for_invite, s.created_at,
+COALESCE(a.approximate_votes, 0) AS approximate_votes";
+
+const SERVER_SUMMARY_COLS: &str = "s.server_id, s.name, v.code::TEXT AS vanity, s.short, s.tags, s.nsfw, s.premium,
+s.total_members, s.state, s.type, COALESCE(a.approximate_votes, 0) AS approximate_votes";
+
+/// Expressions used when listing servers
+const SERVER_LIST_KEYS: ListKeys<'static> = ListKeys {
+    id: "s.server_id",
+    votes: "COALESCE(a.approximate_votes, 0)",
+    created: "s.created_at",
+    name: "s.name",
+    search: &["s.name", "s.short"],
+};
+
+/// The full server object. Internal use only, never return this in public API responses
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct ServerFull {
+    pub server_id: String,
+    pub name: String,
+    pub vanity: Option<String>,
+    pub team_owner: uuid::Uuid,
+    pub short: String,
+    pub long: String,
+    pub invite: String,
+    pub tags: Vec<String>,
+    #[schema(value_type = Object)]
+    pub extra_links: serde_json::Value,
+    /// The visibility state of the server (public, private etc.)
+    pub state: String,
+    /// The review state of the server (approved, pending, certified, banned etc.)
+    #[sqlx(rename = "type")]
+    pub server_type: String,
+    pub nsfw: bool,
+    pub premium: bool,
+    pub start_premium_period: DateTime<Utc>,
+    /// Length of the premium period in seconds
+    pub premium_period_length: i64,
+    pub total_members: i32,
+    pub online_members: i32,
+    pub clicks: i32,
+    pub invite_clicks: i32,
+    pub vote_banned: bool,
+    pub captcha_opt_out: bool,
+    pub login_required_for_invite: bool,
+    pub blacklisted_users: Vec<String>,
+    pub claimed_by: Option<String>,
+    pub last_claimed: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub approximate_votes: i32,
+}
+
+/// The public server object, used in API responses
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct ServerPublic {
+    pub server_id: String,
+    pub name: String,
+    pub vanity: Option<String>,
+    pub team_owner: uuid::Uuid,
+    pub short: String,
+    pub long: String,
+    pub tags: Vec<String>,
+    #[schema(value_type = Object)]
+    pub extra_links: serde_json::Value,
+    /// The visibility state of the server (public, private etc.)
+    pub state: String,
+    /// The review state of the server (approved, pending, certified, banned etc.)
+    #[sqlx(rename = "type")]
+    pub server_type: String,
+    pub nsfw: bool,
+    pub premium: bool,
+    pub total_members: i32,
+    pub online_members: i32,
+    pub clicks: i32,
+    pub invite_clicks: i32,
+    pub vote_banned: bool,
+    pub login_required_for_invite: bool,
+    pub created_at: DateTime<Utc>,
+    pub approximate_votes: i32,
+}
+
+/// The summary (short form) server object, used in listings
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct ServerSummary {
+    pub server_id: String,
+    pub name: String,
+    pub vanity: Option<String>,
+    pub short: String,
+    pub tags: Vec<String>,
+    pub nsfw: bool,
+    pub premium: bool,
+    pub total_members: i32,
+    /// The visibility state of the server (public, private etc.)
+    pub state: String,
+    /// The review state of the server (approved, pending, certified, banned etc.)
+    #[sqlx(rename = "type")]
+    pub server_type: String,
+    pub approximate_votes: i32,
+}
+
+/// Data needed to add a new server
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct ServerCreate {
+    pub server_id: String,
+    pub name: String,
+    pub team_owner: uuid::Uuid,
+    pub short: String,
+    pub long: String,
+    pub invite: String,
+    pub tags: Vec<String>,
+    #[schema(value_type = Object)]
+    pub extra_links: serde_json::Value,
+    pub nsfw: bool,
+    pub total_members: i32,
+    pub online_members: i32,
+}
+
+/// Partial update of a server, None fields are left unchanged
+#[derive(Debug, Clone, Default, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct ServerUpdate {
+    pub name: Option<String>,
+    pub short: Option<String>,
+    pub long: Option<String>,
+    pub invite: Option<String>,
+    pub tags: Option<Vec<String>>,
+    #[schema(value_type = Option<Object>)]
+    pub extra_links: Option<serde_json::Value>,
+    pub nsfw: Option<bool>,
+    pub captcha_opt_out: Option<bool>,
+    pub login_required_for_invite: Option<bool>,
+}
+
+/// Filters for listing servers
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerListFilter {
+    /// Only return servers with this visibility state (public, private etc.)
+    pub state: Option<String>,
+    /// Only return servers with this review state (approved, certified etc.)
+    pub server_type: Option<String>,
+    pub nsfw: Option<bool>,
+    pub premium: Option<bool>,
+    /// Only return servers with all of these tags
+    pub tags: Option<Vec<String>>,
+    /// Only return servers owned by this team
+    pub team_owner: Option<uuid::Uuid>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Server {
+    pool: sqlx::PgPool,
+    diesel: crate::Db,
+}
+
+impl Server {
+    /// Creates a new instance of the Server entity.
+    pub fn new(pool: sqlx::PgPool, diesel: crate::Db) -> Self {
+        Self { pool, diesel }
+    }
+}
+
+impl Entity for Server {
+    type FullObject = ServerFull;
+    type PublicObject = ServerPublic;
+    type SummaryObject = ServerSummary;
+    type CreateObject = ServerCreate;
+    type UpdateObject = ServerUpdate;
+    type ListFilter = ServerListFilter;
+
+    fn pool(&self) -> &sqlx::PgPool {
+        &self.pool
+    }
+
+    fn diesel(&self) -> &crate::Db {
+        &self.diesel
+    }
+
+    fn name(&self) -> &'static str {
+        "Server"
+    }
+
+    fn target_type(&self) -> &'static str {
+        "server"
+    }
+
+    fn cdn_folder(&self) -> &'static str {
+        "servers"
+    }
+
+    async fn flags(&self, id: &str) -> Result<EntityFlags, crate::Error> {
+        #[derive(sqlx::FromRow)]
+        struct ServerFlagsRow {
+            #[sqlx(rename = "type")]
+            server_type: String,
+            vote_banned: bool,
+        }
+
+        let row: Option<ServerFlagsRow> = sqlx::query_as("SELECT type, vote_banned FROM servers WHERE server_id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        let Some(row) = row else {
+            return Ok(EntityFlags::NONE);
+        };
+
+        let mut flags = EntityFlags::SUPPORTS_WEBHOOKS
+            | EntityFlags::SUPPORTS_MULTIPLE_VOTES
+            | EntityFlags::SUPPORTS_UPVOTES
+            | EntityFlags::SUPPORTS_VOTE_CREDITS;
+
+        if !row.vote_banned {
+            flags |= EntityFlags::SUPPORTS_VOTING;
+        }
+
+        if row.server_type == "banned" {
+            flags |= EntityFlags::BANNED;
+        }
+
+        Ok(flags)
+    }
+
+    async fn get_info(&self, id: &str) -> Result<Option<EntityInfo>, crate::Error> {
+        #[derive(sqlx::FromRow)]
+        struct ServerInfoRow {
+            name: String,
+            vanity: Option<String>,
+        }
+
+        let row: Option<ServerInfoRow> = sqlx::query_as(&format!(
+            "SELECT s.name, v.code::TEXT AS vanity {SERVER_JOINS} WHERE s.server_id = $1"
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let slug = row.vanity.as_deref().unwrap_or(id);
+
+        Ok(Some(EntityInfo {
+            name: row.name,
+            url: format!("{}/server/{}", CONFIG.frontend_url, slug),
+            vote_url: Some(format!("{}/server/{}/vote", CONFIG.frontend_url, slug)),
+            avatar: Some(resolve_asset_metadata_to_url(&asset_metadata(self, id, AssetType::Avatar))),
+        }))
+    }
+
+    async fn get_vote_info(&self, id: &str, user_id: Option<&str>) -> Result<EntityVoteInfo, crate::Error> {
+        let premium: Option<bool> = sqlx::query_scalar("SELECT premium FROM servers WHERE server_id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        let Some(premium) = premium else {
+            return Err("Server not found".into());
+        };
+
+        compute_vote_info(&self.pool, self.target_type(), id, premium, user_id).await
+    }
+
+    async fn get_full(&self, id: &str) -> Result<Self::FullObject, crate::Error> {
+        let server: Option<ServerFull> = sqlx::query_as(&format!("SELECT {SERVER_FULL_COLS} {SERVER_JOINS} WHERE s.server_id = $1"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        server.ok_or_else(|| "Server not found".into())
+    }
+
+    async fn get_public(&self, id: &str) -> Result<Self::PublicObject, crate::Error> {
+        let server: Option<ServerPublic> = sqlx::query_as(&format!("SELECT {SERVER_PUBLIC_COLS} {SERVER_JOINS} WHERE s.server_id = $1"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        server.ok_or_else(|| "Server not found".into())
+    }
+
+    async fn get_summary(&self, id: &str) -> Result<Self::SummaryObject, crate::Error> {
+        let server: Option<ServerSummary> = sqlx::query_as(&format!("SELECT {SERVER_SUMMARY_COLS} {SERVER_JOINS} WHERE s.server_id = $1"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        server.ok_or_else(|| "Server not found".into())
+    }
+
+    async fn create(&self, conn: &mut sqlx::PgConnection, obj: Self::CreateObject) -> Result<String, crate::Error> {
+        let exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM servers WHERE server_id = $1)")
+            .bind(&obj.server_id)
+            .fetch_one(&mut *conn)
+            .await?;
+
+        if exists {
+            return Err("Server already exists".into());
+        }
+
+        // Every server needs a vanity, default to the server ID
+        let vanity_ref: uuid::Uuid = sqlx::query_scalar(
+            "INSERT INTO vanity (target_id, target_type, code) VALUES ($1, 'server', $1) RETURNING itag",
+        )
+        .bind(&obj.server_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        sqlx::query(
+            "INSERT INTO servers (server_id, name, team_owner, short, long, invite, tags, extra_links, nsfw, total_members, online_members, vanity_ref)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+        )
+        .bind(&obj.server_id)
+        .bind(&obj.name)
+        .bind(obj.team_owner)
+        .bind(&obj.short)
+        .bind(&obj.long)
+        .bind(&obj.invite)
+        .bind(&obj.tags)
+        .bind(&obj.extra_links)
+        .bind(obj.nsfw)
+        .bind(obj.total_members)
+        .bind(obj.online_members)
+        .bind(vanity_ref)
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(obj.server_id)
+    }
+
+    async fn resolve_id(&self, id: &str) -> Result<Option<String>, crate::Error> {
+        // IDs take priority over vanity codes, which default to the ID anyways
+        let server_id: Option<String> = sqlx::query_scalar(
+            "SELECT server_id FROM servers WHERE server_id = $1
+            UNION ALL SELECT target_id FROM vanity WHERE code = $1 AND target_type = 'server'
+            LIMIT 1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(server_id)
+    }
+
+    async fn update(&self, id: &str, obj: Self::UpdateObject) -> Result<(), crate::Error> {
+        // Servers have no updated_at column, so the first SET is a no-op to keep the query valid for empty updates
+        let mut qb = sqlx::QueryBuilder::new("UPDATE servers SET server_id = server_id");
+        if let Some(name) = obj.name {
+            qb.push(", name = ").push_bind(name);
+        }
+        if let Some(short) = obj.short {
+            qb.push(", short = ").push_bind(short);
+        }
+        if let Some(long) = obj.long {
+            qb.push(", long = ").push_bind(long);
+        }
+        if let Some(invite) = obj.invite {
+            qb.push(", invite = ").push_bind(invite);
+        }
+        if let Some(tags) = obj.tags {
+            qb.push(", tags = ").push_bind(tags);
+        }
+        if let Some(extra_links) = obj.extra_links {
+            qb.push(", extra_links = ").push_bind(extra_links);
+        }
+        if let Some(nsfw) = obj.nsfw {
+            qb.push(", nsfw = ").push_bind(nsfw);
+        }
+        if let Some(captcha_opt_out) = obj.captcha_opt_out {
+            qb.push(", captcha_opt_out = ").push_bind(captcha_opt_out);
+        }
+        if let Some(login_required_for_invite) = obj.login_required_for_invite {
+            qb.push(", login_required_for_invite = ").push_bind(login_required_for_invite);
+        }
+        qb.push(" WHERE server_id = ").push_bind(id.to_string());
+
+        let res = qb.build().execute(&self.pool).await?;
+
+        if res.rows_affected() == 0 {
+            return Err("Server not found".into());
+        }
+
+        Ok(())
+    }
+
+    async fn delete(&self, conn: &mut sqlx::PgConnection, id: &str) -> Result<(), crate::Error> {
+        let res = sqlx::query("DELETE FROM servers WHERE server_id = $1")
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+
+        if res.rows_affected() == 0 {
+            return Err("Server not found".into());
+        }
+
+        sqlx::query("DELETE FROM vanity WHERE target_id = $1 AND target_type = 'server'")
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn list(&self, query: EntityListQuery<Self::ListFilter>) -> Result<EntityList<Self::SummaryObject>, crate::Error> {
+        let mut qb = sqlx::QueryBuilder::new(format!(
+            "SELECT {SERVER_SUMMARY_COLS}, {} {SERVER_JOINS} WHERE TRUE",
+            cursor_cols(query.sort, &SERVER_LIST_KEYS)
+        ));
+
+        let filter = &query.filter;
+        if let Some(state) = &filter.state {
+            qb.push(" AND s.state = ").push_bind(state.clone());
+        }
+        if let Some(server_type) = &filter.server_type {
+            qb.push(" AND s.type = ").push_bind(server_type.clone());
+        }
+        if let Some(nsfw) = filter.nsfw {
+            qb.push(" AND s.nsfw = ").push_bind(nsfw);
+        }
+        if let Some(premium) = filter.premium {
+            qb.push(" AND s.premium = ").push_bind(premium);
+        }
+        if let Some(tags) = &filter.tags {
+            qb.push(" AND s.tags @> ").push_bind(tags.clone());
+        }
+        if let Some(team_owner) = filter.team_owner {
+            qb.push(" AND s.team_owner = ").push_bind(team_owner);
+        }
+
+        fetch_page(qb, &query, &SERVER_LIST_KEYS, &self.pool).await
+    }
+}
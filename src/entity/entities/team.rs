@@ -0,0 +1,380 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config::CONFIG;
+use crate::entity::list::{EntityList, EntityListQuery, ListKeys, cursor_cols, fetch_page};
+use crate::entity::{Entity, EntityFlags, EntityInfo};
+use crate::utils::asset::{AssetType, asset_metadata, resolve_asset_metadata_to_url};
+
+/// Joins shared by all team object queries
+const TEAM_JOINS: &str = "FROM teams t
+LEFT JOIN vanity v ON v.itag = t.vanity_ref
+LEFT JOIN entity_approx_votes a ON a.target_id = t.id::TEXT AND a.target_type = 'team'";
+
+const TEAM_FULL_COLS: &str = "t.id, t.name, v.code::TEXT AS vanity, t.short, t.tags, t.extra_links, t.nsfw, t.vote_banned,
+t.service, t.created_at, t.updated_at, COALESCE(a.approximate_votes, 0) AS approximate_votes";
+
+const TEAM_PUBLIC_COLS: &str = "t.id, t.name, v.code::TEXT AS vanity, t.short, t.tags, t.extra_links, t.nsfw, t.vote_banned,
+t.created_at, COALESCE(a.approximate_votes, 0) AS approximate_votes";
+
+const TEAM_SUMMARY_COLS: &str = "t.id, t.name, v.code::TEXT AS vanity, t.short, t.tags, t.nsfw,
+COALESCE(a.approximate_votes, 0) AS approximate_votes";
+
+/// Expressions used when listing teams
+const TEAM_LIST_KEYS: ListKeys<'static> = ListKeys {
+    id: "t.id::TEXT",
+    votes: "COALESCE(a.approximate_votes, 0)",
+    created: "t.created_at",
+    name: "t.name",
+    search: &["t.name", "COALESCE(t.short, '')"],
+};
+
+/// The full team object. Internal use only, never return this in public API responses
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct TeamFull {
+    pub id: uuid::Uuid,
+    pub name: String,
+    pub vanity: Option<String>,
+    pub short: Option<String>,
+    pub tags: Option<Vec<String>>,
+    #[schema(value_type = Object)]
+    pub extra_links: serde_json::Value,
+    pub nsfw: bool,
+    pub vote_banned: bool,
+    /// The service the team was created through
+    pub service: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub approximate_votes: i32,
+}
+
+/// The public team object, used in API responses
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct TeamPublic {
+    pub id: uuid::Uuid,
+    pub name: String,
+    pub vanity: Option<String>,
+    pub short: Option<String>,
+    pub tags: Option<Vec<String>>,
+    #[schema(value_type = Object)]
+    pub extra_links: serde_json::Value,
+    pub nsfw: bool,
+    pub vote_banned: bool,
+    pub created_at: DateTime<Utc>,
+    pub approximate_votes: i32,
+}
+
+/// The summary (short form) team object, used in listings
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct TeamSummary {
+    pub id: uuid::Uuid,
+    pub name: String,
+    pub vanity: Option<String>,
+    pub short: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub nsfw: bool,
+    pub approximate_votes: i32,
+}
+
+/// Data needed to create a new team
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct TeamCreate {
+    pub name: String,
+    /// The user who will own the team
+    pub owner: String,
+    pub short: Option<String>,
+    pub tags: Option<Vec<String>>,
+    #[schema(value_type = Object)]
+    pub extra_links: serde_json::Value,
+    pub nsfw: bool,
+}
+
+/// Partial update of a team, None fields are left unchanged
+#[derive(Debug, Clone, Default, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct TeamUpdate {
+    pub name: Option<String>,
+    pub short: Option<String>,
+    pub tags: Option<Vec<String>>,
+    #[schema(value_type = Option<Object>)]
+    pub extra_links: Option<serde_json::Value>,
+    pub nsfw: Option<bool>,
+}
+
+/// Filters for listing teams
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TeamListFilter {
+    pub nsfw: Option<bool>,
+    /// Only return teams with all of these tags
+    pub tags: Option<Vec<String>>,
+    /// Only return teams this user is a member of
+    pub member: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Team {
+    pool: sqlx::PgPool,
+    diesel: crate::Db,
+}
+
+impl Team {
+    /// Creates a new instance of the Team entity.
+    pub fn new(pool: sqlx::PgPool, diesel: crate::Db) -> Self {
+        Self { pool, diesel }
+    }
+}
+
+/// Team IDs are UUIDs, anything else can never match a team
+fn parse_team_id(id: &str) -> Option<uuid::Uuid> {
+    uuid::Uuid::parse_str(id).ok()
+}
+
+impl Entity for Team {
+    type FullObject = TeamFull;
+    type PublicObject = TeamPublic;
+    type SummaryObject = TeamSummary;
+    type CreateObject = TeamCreate;
+    type UpdateObject = TeamUpdate;
+    type ListFilter = TeamListFilter;
+
+    fn pool(&self) -> &sqlx::PgPool {
+        &self.pool
+    }
+
+    fn diesel(&self) -> &crate::Db {
+        &self.diesel
+    }
+
+    fn name(&self) -> &'static str {
+        "Team"
+    }
+
+    fn target_type(&self) -> &'static str {
+        "team"
+    }
+
+    fn cdn_folder(&self) -> &'static str {
+        "teams"
+    }
+
+    async fn flags(&self, id: &str) -> Result<EntityFlags, crate::Error> {
+        let Some(id) = parse_team_id(id) else {
+            return Ok(EntityFlags::NONE);
+        };
+
+        let vote_banned: Option<bool> = sqlx::query_scalar("SELECT vote_banned FROM teams WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        let Some(vote_banned) = vote_banned else {
+            return Ok(EntityFlags::NONE);
+        };
+
+        let mut flags = EntityFlags::SUPPORTS_WEBHOOKS
+            | EntityFlags::SUPPORTS_MULTIPLE_VOTES
+            | EntityFlags::SUPPORTS_UPVOTES;
+
+        if !vote_banned {
+            flags |= EntityFlags::SUPPORTS_VOTING;
+        }
+
+        Ok(flags)
+    }
+
+    async fn get_info(&self, id: &str) -> Result<Option<EntityInfo>, crate::Error> {
+        #[derive(sqlx::FromRow)]
+        struct TeamInfoRow {
+            name: String,
+            vanity: Option<String>,
+        }
+
+        let Some(team_id) = parse_team_id(id) else {
+            return Ok(None);
+        };
+
+        let row: Option<TeamInfoRow> = sqlx::query_as(&format!(
+            "SELECT t.name, v.code::TEXT AS vanity {TEAM_JOINS} WHERE t.id = $1"
+        ))
+        .bind(team_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let slug = row.vanity.as_deref().unwrap_or(id);
+
+        Ok(Some(EntityInfo {
+            name: row.name,
+            url: format!("{}/team/{}", CONFIG.frontend_url, slug),
+            vote_url: Some(format!("{}/team/{}/vote", CONFIG.frontend_url, slug)),
+            avatar: Some(resolve_asset_metadata_to_url(&asset_metadata(self, id, AssetType::Avatar))),
+        }))
+    }
+
+    async fn get_full(&self, id: &str) -> Result<Self::FullObject, crate::Error> {
+        let team_id = parse_team_id(id).ok_or("Team not found")?;
+        let team: Option<TeamFull> = sqlx::query_as(&format!("SELECT {TEAM_FULL_COLS} {TEAM_JOINS} WHERE t.id = $1"))
+            .bind(team_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        team.ok_or_else(|| "Team not found".into())
+    }
+
+    async fn get_public(&self, id: &str) -> Result<Self::PublicObject, crate::Error> {
+        let team_id = parse_team_id(id).ok_or("Team not found")?;
+        let team: Option<TeamPublic> = sqlx::query_as(&format!("SELECT {TEAM_PUBLIC_COLS} {TEAM_JOINS} WHERE t.id = $1"))
+            .bind(team_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        team.ok_or_else(|| "Team not found".into())
+    }
+
+    async fn get_summary(&self, id: &str) -> Result<Self::SummaryObject, crate::Error> {
+        let team_id = parse_team_id(id).ok_or("Team not found")?;
+        let team: Option<TeamSummary> = sqlx::query_as(&format!("SELECT {TEAM_SUMMARY_COLS} {TEAM_JOINS} WHERE t.id = $1"))
+            .bind(team_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        team.ok_or_else(|| "Team not found".into())
+    }
+
+    async fn create(&self, conn: &mut sqlx::PgConnection, obj: Self::CreateObject) -> Result<String, crate::Error> {
+        let team_id = uuid::Uuid::new_v4();
+
+        // Every team needs a vanity, default to the team ID
+        let vanity_ref: uuid::Uuid = sqlx::query_scalar(
+            "INSERT INTO vanity (target_id, target_type, code) VALUES ($1, 'team', $1) RETURNING itag",
+        )
+        .bind(team_id.to_string())
+        .fetch_one(&mut *conn)
+        .await?;
+
+        sqlx::query(
+            "INSERT INTO teams (id, name, short, tags, extra_links, nsfw, vanity_ref) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(team_id)
+        .bind(&obj.name)
+        .bind(&obj.short)
+        .bind(&obj.tags)
+        .bind(&obj.extra_links)
+        .bind(obj.nsfw)
+        .bind(vanity_ref)
+        .execute(&mut *conn)
+        .await?;
+
+        // The creator of the team gets all permissions on it
+        sqlx::query("INSERT INTO team_members (team_id, user_id, flags) VALUES ($1, $2, $3)")
+            .bind(team_id)
+            .bind(&obj.owner)
+            .bind(vec!["global.*".to_string()])
+            .execute(&mut *conn)
+            .await?;
+
+        Ok(team_id.to_string())
+    }
+
+    async fn update(&self, id: &str, obj: Self::UpdateObject) -> Result<(), crate::Error> {
+        let team_id = parse_team_id(id).ok_or("Team not found")?;
+        let mut qb = sqlx::QueryBuilder::new("UPDATE teams SET updated_at = NOW()");
+        if let Some(name) = obj.name {
+            qb.push(", name = ").push_bind(name);
+        }
+        if let Some(short) = obj.short {
+            qb.push(", short = ").push_bind(short);
+        }
+        if let Some(tags) = obj.tags {
+            qb.push(", tags = ").push_bind(tags);
+        }
+        if let Some(extra_links) = obj.extra_links {
+            qb.push(", extra_links = ").push_bind(extra_links);
+        }
+        if let Some(nsfw) = obj.nsfw {
+            qb.push(", nsfw = ").push_bind(nsfw);
+        }
+        qb.push(" WHERE id = ").push_bind(team_id);
+
+        let res = qb.build().execute(&self.pool).await?;
+
+        if res.rows_affected() == 0 {
+            return Err("Team not found".into());
+        }
+
+        Ok(())
+    }
+
+    async fn resolve_id(&self, id: &str) -> Result<Option<String>, crate::Error> {
+        if let Some(team_id) = parse_team_id(id) {
+            return Ok(Some(team_id.to_string()));
+        }
+
+        let team_id: Option<String> = sqlx::query_scalar("SELECT target_id FROM vanity WHERE code = $1 AND target_type = 'team'")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(team_id)
+    }
+
+    async fn delete(&self, conn: &mut sqlx::PgConnection, id: &str) -> Result<(), crate::Error> {
+        let team_id = parse_team_id(id).ok_or("Team not found")?;
+
+        let owns_entities: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM bots WHERE team_owner = $1) OR EXISTS (SELECT 1 FROM servers WHERE team_owner = $1)",
+        )
+        .bind(team_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        if owns_entities {
+            return Err("Team still owns bots or servers, transfer or delete them first".into());
+        }
+
+        sqlx::query("DELETE FROM team_members WHERE team_id = $1")
+            .bind(team_id)
+            .execute(&mut *conn)
+            .await?;
+
+        let res = sqlx::query("DELETE FROM teams WHERE id = $1")
+            .bind(team_id)
+            .execute(&mut *conn)
+            .await?;
+
+        if res.rows_affected() == 0 {
+            return Err("Team not found".into());
+        }
+
+        sqlx::query("DELETE FROM vanity WHERE target_id = $1 AND target_type = 'team'")
+            .bind(team_id.to_string())
+            .execute(&mut *conn)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn list(&self, query: EntityListQuery<Self::ListFilter>) -> Result<EntityList<Self::SummaryObject>, crate::Error> {
+        let mut qb = sqlx::QueryBuilder::new(format!(
+            "SELECT {TEAM_SUMMARY_COLS}, {} {TEAM_JOINS} WHERE TRUE",
+            cursor_cols(query.sort, &TEAM_LIST_KEYS)
+        ));
+
+        let filter = &query.filter;
+        if let Some(nsfw) = filter.nsfw {
+            qb.push(" AND t.nsfw = ").push_bind(nsfw);
+        }
+        if let Some(tags) = &filter.tags {
+            qb.push(" AND t.tags @> ").push_bind(tags.clone());
+        }
+        if let Some(member) = &filter.member {
+            qb.push(" AND EXISTS (SELECT 1 FROM team_members m WHERE m.team_id = t.id AND m.user_id = ")
+                .push_bind(member.clone())
+                .push(")");
+        }
+
+        fetch_page(qb, &query, &TEAM_LIST_KEYS, &self.pool).await
+    }
+}
@@ -0,0 +1,284 @@
+use chrono::{DateTime, Utc};
+use rand::distr::{Alphanumeric, SampleString};
+use serde::{Deserialize, Serialize};
+
+use crate::config::CONFIG;
+use crate::entity::list::{EntityList, EntityListQuery, ListKeys, cursor_cols, fetch_page};
+use crate::entity::{Entity, EntityFlags, EntityInfo};
+
+/// Joins shared by all user object queries
+///
+/// Names and avatars come from the discord user cache
+const USER_JOINS: &str = "FROM users u
+LEFT JOIN internal_user_cache__discord c ON c.id = u.user_id";
+
+const USER_FULL_COLS: &str = "u.user_id, COALESCE(c.display_name, u.user_id) AS name, COALESCE(c.username, u.user_id) AS username, c.avatar,
+u.experiments, u.certified, u.developer, u.bug_hunters, u.captcha_sponsor_enabled, u.extra_links, u.about,
+u.vote_banned, u.banned, u.app_banned, u.last_booster_claim, u.created_at, u.updated_at";
+
+const USER_PUBLIC_COLS: &str = "u.user_id, COALESCE(c.display_name, u.user_id) AS name, COALESCE(c.username, u.user_id) AS username, c.avatar,
+u.certified, u.developer, u.bug_hunters, u.extra_links, u.about, u.created_at";
+
+const USER_SUMMARY_COLS: &str = "u.user_id, COALESCE(c.display_name, u.user_id) AS name, c.avatar, u.certified";
+
+/// Expressions used when listing users
+///
+/// Users cannot be voted on so sorting by votes falls back to the user ID
+const USER_LIST_KEYS: ListKeys<'static> = ListKeys {
+    id: "u.user_id",
+    votes: "0",
+    created: "u.created_at",
+    name: "COALESCE(c.display_name, u.user_id)",
+    search: &["COALESCE(c.display_name, u.user_id)", "COALESCE(c.username, u.user_id)"],
+};
+
+/// The full user object. Internal use only, never return this in public API responses
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct UserFull {
+    pub user_id: String,
+    pub name: String,
+    pub username: String,
+    pub avatar: Option<String>,
+    pub experiments: Vec<String>,
+    pub certified: bool,
+    pub developer: bool,
+    pub bug_hunters: bool,
+    pub captcha_sponsor_enabled: bool,
+    #[schema(value_type = Object)]
+    pub extra_links: serde_json::Value,
+    pub about: Option<String>,
+    pub vote_banned: bool,
+    pub banned: bool,
+    pub app_banned: bool,
+    pub last_booster_claim: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The public user object, used in API responses
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct UserPublic {
+    pub user_id: String,
+    pub name: String,
+    pub username: String,
+    pub avatar: Option<String>,
+    pub certified: bool,
+    pub developer: bool,
+    pub bug_hunters: bool,
+    #[schema(value_type = Object)]
+    pub extra_links: serde_json::Value,
+    pub about: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The summary (short form) user object, used in listings
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct UserSummary {
+    pub user_id: String,
+    pub name: String,
+    pub avatar: Option<String>,
+    pub certified: bool,
+}
+
+/// Data needed to add a new user
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct UserCreate {
+    pub user_id: String,
+}
+
+/// Partial update of a user, None fields are left unchanged
+#[derive(Debug, Clone, Default, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct UserUpdate {
+    pub about: Option<String>,
+    #[schema(value_type = Option<Object>)]
+    pub extra_links: Option<serde_json::Value>,
+    pub captcha_sponsor_enabled: Option<bool>,
+}
+
+/// Filters for listing users
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UserListFilter {
+    pub certified: Option<bool>,
+    pub developer: Option<bool>,
+    pub bug_hunters: Option<bool>,
+}
+
+#[derive(Debug, Clone)]
+pub struct User {
+    pool: sqlx::PgPool,
+    diesel: crate::Db,
+}
+
+impl User {
+    /// Creates a new instance of the User entity.
+    pub fn new(pool: sqlx::PgPool, diesel: crate::Db) -> Self {
+        Self { pool, diesel }
+    }
+}
+
+impl Entity for User {
+    type FullObject = UserFull;
+    type PublicObject = UserPublic;
+    type SummaryObject = UserSummary;
+    type CreateObject = UserCreate;
+    type UpdateObject = UserUpdate;
+    type ListFilter = UserListFilter;
+
+    fn pool(&self) -> &sqlx::PgPool {
+        &self.pool
+    }
+
+    fn diesel(&self) -> &crate::Db {
+        &self.diesel
+    }
+
+    fn name(&self) -> &'static str {
+        "User"
+    }
+
+    fn target_type(&self) -> &'static str {
+        "user"
+    }
+
+    fn cdn_folder(&self) -> &'static str {
+        "users"
+    }
+
+    async fn flags(&self, id: &str) -> Result<EntityFlags, crate::Error> {
+        let banned: Option<bool> = sqlx::query_scalar("SELECT banned FROM users WHERE user_id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        // Users cannot be voted on, so the only flag that applies is BANNED
+        match banned {
+            Some(true) => Ok(EntityFlags::BANNED),
+            _ => Ok(EntityFlags::NONE),
+        }
+    }
+
+    async fn get_info(&self, id: &str) -> Result<Option<EntityInfo>, crate::Error> {
+        #[derive(sqlx::FromRow)]
+        struct UserInfoRow {
+            name: String,
+            avatar: Option<String>,
+        }
+
+        let row: Option<UserInfoRow> = sqlx::query_as(&format!(
+            "SELECT COALESCE(c.display_name, u.user_id) AS name, c.avatar {USER_JOINS} WHERE u.user_id = $1"
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some(EntityInfo {
+            name: row.name,
+            url: format!("{}/users/{}", CONFIG.frontend_url, id),
+            vote_url: None,
+            avatar: row.avatar,
+        }))
+    }
+
+    async fn get_full(&self, id: &str) -> Result<Self::FullObject, crate::Error> {
+        let user: Option<UserFull> = sqlx::query_as(&format!("SELECT {USER_FULL_COLS} {USER_JOINS} WHERE u.user_id = $1"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        user.ok_or_else(|| "User not found".into())
+    }
+
+    async fn get_public(&self, id: &str) -> Result<Self::PublicObject, crate::Error> {
+        let user: Option<UserPublic> = sqlx::query_as(&format!("SELECT {USER_PUBLIC_COLS} {USER_JOINS} WHERE u.user_id = $1"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        user.ok_or_else(|| "User not found".into())
+    }
+
+    async fn get_summary(&self, id: &str) -> Result<Self::SummaryObject, crate::Error> {
+        let user: Option<UserSummary> = sqlx::query_as(&format!("SELECT {USER_SUMMARY_COLS} {USER_JOINS} WHERE u.user_id = $1"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        user.ok_or_else(|| "User not found".into())
+    }
+
+    async fn create(&self, conn: &mut sqlx::PgConnection, obj: Self::CreateObject) -> Result<String, crate::Error> {
+        let api_token = Alphanumeric.sample_string(&mut rand::rng(), 128);
+
+        let res = sqlx::query("INSERT INTO users (user_id, api_token) VALUES ($1, $2) ON CONFLICT (user_id) DO NOTHING")
+            .bind(&obj.user_id)
+            .bind(&api_token)
+            .execute(&mut *conn)
+            .await?;
+
+        if res.rows_affected() == 0 {
+            return Err("User already exists".into());
+        }
+
+        Ok(obj.user_id)
+    }
+
+    async fn update(&self, id: &str, obj: Self::UpdateObject) -> Result<(), crate::Error> {
+        let mut qb = sqlx::QueryBuilder::new("UPDATE users SET updated_at = NOW()");
+        if let Some(about) = obj.about {
+            qb.push(", about = ").push_bind(about);
+        }
+        if let Some(extra_links) = obj.extra_links {
+            qb.push(", extra_links = ").push_bind(extra_links);
+        }
+        if let Some(captcha_sponsor_enabled) = obj.captcha_sponsor_enabled {
+            qb.push(", captcha_sponsor_enabled = ").push_bind(captcha_sponsor_enabled);
+        }
+        qb.push(" WHERE user_id = ").push_bind(id.to_string());
+
+        let res = qb.build().execute(&self.pool).await?;
+
+        if res.rows_affected() == 0 {
+            return Err("User not found".into());
+        }
+
+        Ok(())
+    }
+
+    async fn delete(&self, conn: &mut sqlx::PgConnection, id: &str) -> Result<(), crate::Error> {
+        // Votes made by the user and other user owned rows cascade from users
+        let res = sqlx::query("DELETE FROM users WHERE user_id = $1")
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+
+        if res.rows_affected() == 0 {
+            return Err("User not found".into());
+        }
+
+        Ok(())
+    }
+
+    async fn list(&self, query: EntityListQuery<Self::ListFilter>) -> Result<EntityList<Self::SummaryObject>, crate::Error> {
+        let mut qb = sqlx::QueryBuilder::new(format!(
+            "SELECT {USER_SUMMARY_COLS}, {} {USER_JOINS} WHERE TRUE",
+            cursor_cols(query.sort, &USER_LIST_KEYS)
+        ));
+
+        let filter = &query.filter;
+        if let Some(certified) = filter.certified {
+            qb.push(" AND u.certified = ").push_bind(certified);
+        }
+        if let Some(developer) = filter.developer {
+            qb.push(" AND u.developer = ").push_bind(developer);
+        }
+        if let Some(bug_hunters) = filter.bug_hunters {
+            qb.push(" AND u.bug_hunters = ").push_bind(bug_hunters);
+        }
+
+        fetch_page(qb, &query, &USER_LIST_KEYS, &self.pool).await
+    }
+}
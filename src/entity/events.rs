@@ -0,0 +1,61 @@
+use std::sync::LazyLock;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+/// How many events a slow subscriber can fall behind by before it starts missing events
+const VOTE_EVENT_CAPACITY: usize = 1024;
+
+/// Process wide bus of vote events, shared by every LayerThread
+static VOTE_EVENTS: LazyLock<broadcast::Sender<VoteCast>> = LazyLock::new(|| broadcast::channel(VOTE_EVENT_CAPACITY).0);
+
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+/// Emitted once votes have been committed for an entity
+pub struct VoteCast {
+    /// The ID of the entity that was voted on
+    pub target_id: String,
+    /// The type of the entity that was voted on
+    pub target_type: String,
+    /// The user who voted
+    pub user_id: String,
+    /// The amount of votes that were given
+    pub votes: u8,
+    pub upvote: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Publishes a vote event to all current subscribers
+///
+/// Events published while nothing is subscribed are dropped
+pub fn publish_vote(event: VoteCast) {
+    let _ = VOTE_EVENTS.send(event);
+}
+
+/// Subscribes to vote events published after this call
+pub fn subscribe_votes() -> broadcast::Receiver<VoteCast> {
+    VOTE_EVENTS.subscribe()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_vote_events() {
+        let mut rx = subscribe_votes();
+
+        publish_vote(VoteCast {
+            target_id: "1".to_string(),
+            target_type: "bot".to_string(),
+            user_id: "2".to_string(),
+            votes: 2,
+            upvote: true,
+            created_at: Utc::now(),
+        });
+
+        let event = rx.recv().await.unwrap();
+        assert_eq!(event.target_id, "1");
+        assert_eq!(event.votes, 2);
+    }
+}
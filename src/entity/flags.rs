@@ -0,0 +1,186 @@
+use bitflags::Flags;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::EntityFlags;
+
+/// The kittycat permission needed to set flags on an entity
+pub const SET_FLAGS_PERM: &str = "entity_flags.set";
+/// The kittycat permission needed to clear flags on an entity
+pub const CLEAR_FLAGS_PERM: &str = "entity_flags.clear";
+
+/// The flag overrides stored for an entity in entity_flags
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FlagOverrides {
+    /// Flags forced on
+    pub set: EntityFlags,
+    /// Flags forced off, takes priority over set
+    pub cleared: EntityFlags,
+}
+
+impl FlagOverrides {
+    pub fn from_bits(set: i64, cleared: i64) -> Self {
+        Self {
+            set: EntityFlags::from_bits_truncate(set as u32),
+            cleared: EntityFlags::from_bits_truncate(cleared as u32),
+        }
+    }
+
+    /// Merges the overrides with the flags of the entity type
+    pub fn resolve(&self, defaults: EntityFlags) -> EntityFlags {
+        (defaults | self.set).difference(self.cleared)
+    }
+
+    /// Applies an action to the overrides, returning the new overrides
+    pub fn apply(self, action: FlagAction, flags: EntityFlags) -> Self {
+        match action {
+            FlagAction::Set => Self {
+                set: self.set | flags,
+                cleared: self.cleared.difference(flags),
+            },
+            FlagAction::Clear => Self {
+                set: self.set.difference(flags),
+                cleared: self.cleared | flags,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+/// A change to the flags of an entity
+pub enum FlagAction {
+    /// Force the flags on
+    Set,
+    /// Force the flags off
+    Clear,
+}
+
+impl FlagAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Set => "set",
+            Self::Clear => "clear",
+        }
+    }
+
+    /// The kittycat permission needed to perform this action
+    pub fn perm(&self) -> &'static str {
+        match self {
+            Self::Set => SET_FLAGS_PERM,
+            Self::Clear => CLEAR_FLAGS_PERM,
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Errors returned by SharedLayer::change_entity_flags and EntityManager::set_flags/clear_flags before any flags are changed
+///
+/// These are boxed into crate::Error and can be recovered with downcast_ref
+pub enum ChangeFlagsError {
+    /// The actor lacks the staff permission needed for the action
+    MissingPerm(FlagAction),
+    /// The target type is not a known entity type
+    UnknownTargetType(String),
+    /// The entity does not exist, holds the entity name and ID
+    EntityNotFound(String, String),
+    /// No reason was given for the change
+    MissingReason,
+}
+
+impl std::fmt::Display for ChangeFlagsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingPerm(action) => write!(f, "You need the {} permission to {} entity flags", action.perm(), action.as_str()),
+            Self::UnknownTargetType(target_type) => write!(f, "Unknown target type: {target_type}"),
+            Self::EntityNotFound(name, id) => write!(f, "{name} {id} does not exist"),
+            Self::MissingReason => write!(f, "A reason must be provided when changing entity flags"),
+        }
+    }
+}
+
+impl std::error::Error for ChangeFlagsError {}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+/// A logged change to the flags of an entity
+pub struct EntityFlagLog {
+    pub id: Uuid,
+    /// The ID of the entity
+    pub target_id: String,
+    /// The type of the entity
+    pub target_type: String,
+    /// The user who made the change
+    pub actor_id: String,
+    /// Either set or clear
+    pub action: String,
+    /// The bits that were set or cleared
+    pub flags: i64,
+    /// The resolved flags of the entity before the change
+    pub old_flags: i64,
+    /// The resolved flags of the entity after the change
+    pub new_flags: i64,
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Returns the names of the flags that are set
+pub fn flag_names(flags: EntityFlags) -> Vec<String> {
+    EntityFlags::FLAGS
+        .iter()
+        .filter(|flag| !flag.value().is_empty() && flags.contains(*flag.value()))
+        .map(|flag| flag.name().to_string())
+        .collect()
+}
+
+/// Parses a list of flag names into flags
+pub fn parse_flag_names(names: &[String]) -> Result<EntityFlags, crate::Error> {
+    let mut flags = EntityFlags::NONE;
+    for name in names {
+        let Some(flag) = EntityFlags::from_name(name) else {
+            return Err(format!("Unknown flag: {name}").into());
+        };
+        flags |= flag;
+    }
+
+    Ok(flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flag_overrides() {
+        let defaults = EntityFlags::SUPPORTS_VOTING | EntityFlags::SUPPORTS_UPVOTES;
+
+        let overrides = FlagOverrides::default();
+        assert_eq!(overrides.resolve(defaults), defaults);
+
+        let overrides = overrides.apply(FlagAction::Set, EntityFlags::BANNED);
+        assert_eq!(overrides.resolve(defaults), defaults | EntityFlags::BANNED);
+
+        // Clearing a default flag forces it off
+        let overrides = overrides.apply(FlagAction::Clear, EntityFlags::SUPPORTS_VOTING);
+        assert_eq!(overrides.resolve(defaults), EntityFlags::SUPPORTS_UPVOTES | EntityFlags::BANNED);
+
+        // Clearing a set flag removes the override
+        let overrides = overrides.apply(FlagAction::Clear, EntityFlags::BANNED);
+        assert_eq!(overrides.set, EntityFlags::NONE);
+        assert_eq!(overrides.resolve(defaults), EntityFlags::SUPPORTS_UPVOTES);
+
+        // Setting a cleared flag again takes priority over the earlier clear
+        let overrides = overrides.apply(FlagAction::Set, EntityFlags::SUPPORTS_VOTING);
+        assert_eq!(overrides.cleared, EntityFlags::BANNED);
+        assert_eq!(overrides.resolve(defaults), defaults);
+    }
+
+    #[test]
+    fn test_flag_names() {
+        let flags = EntityFlags::BANNED | EntityFlags::SUPPORTS_WEBHOOKS;
+        let names = flag_names(flags);
+        assert_eq!(names, vec!["SUPPORTS_WEBHOOKS".to_string(), "BANNED".to_string()]);
+        assert_eq!(parse_flag_names(&names).unwrap(), flags);
+        assert!(parse_flag_names(&["NOT_A_FLAG".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,179 @@
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Row, postgres::PgRow};
+
+/// The default amount of entities returned per page
+pub const DEFAULT_LIST_LIMIT: i64 = 25;
+/// The maximum amount of entities returned per page
+pub const MAX_LIST_LIMIT: i64 = 100;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+/// The key to sort entity listings by
+pub enum EntitySort {
+    /// Most votes first
+    #[default]
+    Votes,
+    /// Newest first
+    Created,
+    /// Alphabetical by name
+    Name,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+/// A request to list entities
+pub struct EntityListQuery<F> {
+    /// Only return entities whose name (or other searchable text) contains this text, case insensitively
+    pub query: Option<String>,
+    /// The key to sort by
+    #[serde(default)]
+    pub sort: EntitySort,
+    /// The cursor returned by the previous page, if any
+    pub cursor: Option<String>,
+    /// The maximum amount of entities to return
+    pub limit: Option<i64>,
+    /// Entity type specific filters
+    #[serde(default)]
+    pub filter: F,
+}
+
+impl<F> EntityListQuery<F> {
+    /// Replaces the filter of the query
+    pub fn with_filter<G>(self, filter: G) -> EntityListQuery<G> {
+        EntityListQuery {
+            query: self.query,
+            sort: self.sort,
+            cursor: self.cursor,
+            limit: self.limit,
+            filter,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, utoipa::ToSchema)]
+/// A page of entities
+pub struct EntityList<S> {
+    pub items: Vec<S>,
+    /// The cursor to pass to fetch the next page, None if this is the last page
+    pub next_cursor: Option<String>,
+}
+
+/// SQL expressions an entity exposes for listing
+pub struct ListKeys<'a> {
+    /// The ID of the entity, as TEXT
+    pub id: &'a str,
+    /// The approximate vote count of the entity
+    pub votes: &'a str,
+    /// When the entity was created
+    pub created: &'a str,
+    /// The display name of the entity
+    pub name: &'a str,
+    /// Expressions searched by the text query
+    pub search: &'a [&'a str],
+}
+
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    sort: EntitySort,
+    key: String,
+    id: String,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).expect("cursor is always serializable");
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json)
+    }
+
+    fn decode(s: &str) -> Result<Self, crate::Error> {
+        let json = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(s)
+            .map_err(|_| "Invalid cursor")?;
+        serde_json::from_slice(&json).map_err(|_| "Invalid cursor".into())
+    }
+}
+
+/// Completes a listing query and fetches a page of entities
+///
+/// `qb` must already select the summary columns and contain a WHERE clause (use `WHERE TRUE` if there are no
+/// type specific filters), the text query, keyset condition, ordering and limit are appended here
+pub async fn fetch_page<'a, F, S>(
+    mut qb: sqlx::QueryBuilder<'a, sqlx::Postgres>,
+    query: &EntityListQuery<F>,
+    keys: &ListKeys<'_>,
+    pool: &sqlx::PgPool,
+) -> Result<EntityList<S>, crate::Error>
+where
+    S: for<'r> FromRow<'r, PgRow>,
+{
+    let (sort_expr, sort_type, descending) = match query.sort {
+        EntitySort::Votes => (keys.votes, "INTEGER", true),
+        EntitySort::Created => (keys.created, "TIMESTAMPTZ", true),
+        EntitySort::Name => (keys.name, "TEXT", false),
+    };
+
+    if let Some(text) = query.query.as_ref().filter(|q| !q.is_empty()) {
+        qb.push(" AND (");
+        for (i, expr) in keys.search.iter().enumerate() {
+            if i > 0 {
+                qb.push(" OR ");
+            }
+            qb.push(format!("strpos(lower({expr}), lower(")).push_bind(text.clone()).push(")) > 0");
+        }
+        qb.push(")");
+    }
+
+    if let Some(cursor) = &query.cursor {
+        let cursor = Cursor::decode(cursor)?;
+        if cursor.sort != query.sort {
+            return Err("Cursor was created for a different sort order".into());
+        }
+
+        let op = if descending { "<" } else { ">" };
+        qb.push(format!(" AND (({sort_expr}), ({})) {op} (", keys.id))
+            .push_bind(cursor.key)
+            .push(format!("::{sort_type}, "))
+            .push_bind(cursor.id)
+            .push(")");
+    }
+
+    let dir = if descending { "DESC" } else { "ASC" };
+    let limit = query.limit.unwrap_or(DEFAULT_LIST_LIMIT).clamp(1, MAX_LIST_LIMIT);
+
+    // Fetch one extra row to know whether there is a next page
+    qb.push(format!(" ORDER BY ({sort_expr}) {dir}, ({}) {dir} LIMIT ", keys.id))
+        .push_bind(limit + 1);
+
+    let rows: Vec<PgRow> = qb.build().fetch_all(pool).await?;
+
+    let has_more = rows.len() as i64 > limit;
+    let mut items = Vec::with_capacity(rows.len().min(limit as usize));
+    let mut next_cursor = None;
+    for (i, row) in rows.iter().take(limit as usize).enumerate() {
+        items.push(S::from_row(row)?);
+
+        if has_more && i as i64 == limit - 1 {
+            next_cursor = Some(
+                Cursor {
+                    sort: query.sort,
+                    key: row.try_get("_cursor_key")?,
+                    id: row.try_get("_cursor_id")?,
+                }
+                .encode(),
+            );
+        }
+    }
+
+    Ok(EntityList { items, next_cursor })
+}
+
+/// Returns the extra columns fetch_page needs to build cursors, to be appended to the selected columns
+pub fn cursor_cols(query_sort: EntitySort, keys: &ListKeys<'_>) -> String {
+    let sort_expr = match query_sort {
+        EntitySort::Votes => keys.votes,
+        EntitySort::Created => keys.created,
+        EntitySort::Name => keys.name,
+    };
+
+    format!("({sort_expr})::TEXT AS _cursor_key, ({})::TEXT AS _cursor_id", keys.id)
+}
@@ -1,16 +1,107 @@
-use crate::entity::{EntityFlags, manager::EntityManager};
+use crate::entity::{flags::flag_names, list::EntityListQuery, manager::{DEFAULT_RECONCILE_BATCH_SIZE, EntityManager}};
+use crate::service::luacore::datetime::DateTime as LuaDateTime;
+use crate::service::sharedlayer::SharedLayerDb;
+use crate::types::votes::{UserVoteHistoryFilter, VoidVotesFilter, VoteBucket};
 
 use super::Entity;
-use bitflags::Flags;
+use chrono::Utc;
 use mluau::prelude::*;
 use mlua_scheduler::LuaSchedulerAsyncUserData;
 
+/// The kittycat permission needed to create an entity from Lua
+pub const CREATE_ENTITY_PERM: &str = "entities.create";
+/// The kittycat permission needed to delete an entity from Lua
+pub const DELETE_ENTITY_PERM: &str = "entities.delete";
+/// The kittycat permission needed to void votes from Lua
+pub const VOID_VOTES_PERM: &str = "votes.void";
+/// The kittycat permission needed to reset votes from Lua
+pub const RESET_VOTES_PERM: &str = "votes.reset";
+/// The kittycat permission needed to redeem the vote credits of an entity from Lua
+pub const REDEEM_VOTE_CREDITS_PERM: &str = "vote_credits.redeem";
+
+// Manually implemented so time ranges can be passed as datetime userdata
+impl FromLua for VoidVotesFilter {
+    fn from_lua(value: LuaValue, _lua: &Lua) -> LuaResult<Self> {
+        let LuaValue::Table(tab) = value else {
+            return Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "VoidVotesFilter".to_string(),
+                message: Some("expected a table".to_string()),
+            });
+        };
+
+        let filter_type: String = tab.get("type")?;
+        match filter_type.as_str() {
+            "Itag" => {
+                let itag: String = tab.get("itag")?;
+                let itag = uuid::Uuid::parse_str(&itag)
+                    .map_err(|e| LuaError::external(format!("Invalid itag: {e}")))?;
+                Ok(VoidVotesFilter::Itag { itag })
+            }
+            "User" => Ok(VoidVotesFilter::User {
+                user_id: tab.get("user_id")?,
+                target_id: tab.get("target_id")?,
+            }),
+            "Entity" => {
+                let from: Option<LuaUserDataRef<LuaDateTime<chrono_tz::Tz>>> = tab.get("from")?;
+                let to: Option<LuaUserDataRef<LuaDateTime<chrono_tz::Tz>>> = tab.get("to")?;
+                Ok(VoidVotesFilter::Entity {
+                    target_id: tab.get("target_id")?,
+                    from: from.map(|dt| dt.dt.with_timezone(&Utc)),
+                    to: to.map(|dt| dt.dt.with_timezone(&Utc)),
+                })
+            }
+            other => Err(LuaError::FromLuaConversionError {
+                from: "table",
+                to: "VoidVotesFilter".to_string(),
+                message: Some(format!("unknown filter type: {other}")),
+            }),
+        }
+    }
+}
+
+// Manually implemented so time ranges can be passed as datetime userdata
+impl FromLua for UserVoteHistoryFilter {
+    fn from_lua(value: LuaValue, _lua: &Lua) -> LuaResult<Self> {
+        let LuaValue::Table(tab) = value else {
+            return Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "UserVoteHistoryFilter".to_string(),
+                message: Some("expected a table".to_string()),
+            });
+        };
+
+        let from: Option<LuaUserDataRef<LuaDateTime<chrono_tz::Tz>>> = tab.get("from")?;
+        let to: Option<LuaUserDataRef<LuaDateTime<chrono_tz::Tz>>> = tab.get("to")?;
+        Ok(UserVoteHistoryFilter {
+            target_type: tab.get("target_type")?,
+            include_void: tab.get::<Option<bool>>("include_void")?.unwrap_or(false),
+            from: from.map(|dt| dt.dt.with_timezone(&Utc)),
+            to: to.map(|dt| dt.dt.with_timezone(&Utc)),
+        })
+    }
+}
+
 /// Wrapper struct to expose EntityManager to Lua
-pub struct LuaEntityManager<T: Entity>(EntityManager<T>);
+/// 
+/// Mutating methods take the ID of the staff member acting as their first argument
+/// and check their kittycat permissions through SharedLayerDb first
+pub struct LuaEntityManager<T: Entity>(EntityManager<T>, SharedLayerDb);
 
 impl<T: Entity> LuaEntityManager<T> {
     pub fn new(manager: EntityManager<T>) -> Self {
-        Self(manager)
+        let db = SharedLayerDb::new(manager.entity().pool().clone(), manager.entity().diesel().clone());
+        Self(manager, db)
+    }
+
+    /// Errors if the actor does not have the given kittycat staff permission
+    async fn require_perm(&self, actor_id: &str, perm: &str) -> LuaResult<()> {
+        let has_perm = self.1.has_staff_perm(actor_id, perm).await.map_err(|e| LuaError::external(e.to_string()))?;
+        if !has_perm {
+            return Err(LuaError::external(format!("You need the {perm} permission to do this")));
+        }
+
+        Ok(())
     }
 }
 
@@ -99,6 +190,158 @@ fetch_votes(
                 Err(e) => Err(LuaError::external(e.to_string())),
             }
         });
+
+        /*
+	/// Checks if a user is allowed to vote on an entity and if so, gives the votes
+	pub async fn cast_vote(&self, id: &str, user_id: &str, upvote: bool) -> Result<CastVoteOutcome, crate::Error> { */
+        methods.add_scheduler_async_method("CastVote", async |lua, this, (id, user_id, upvote): (String, String, bool)| {
+            let res = this.0.cast_vote(&id, &user_id, upvote).await;
+            match res {
+                Ok(outcome) => lua.to_value(&outcome),
+                Err(e) => Err(LuaError::external(e.to_string())),
+            }
+        });
+
+        /*
+	/// Returns the credits an entity would earn by redeeming its votes right now
+	pub async fn get_vote_credits(&self, id: &str) -> Result<VoteCreditBreakdown, crate::Error> { */
+        methods.add_scheduler_async_method("GetVoteCredits", async |lua, this, id: String| {
+            let res = this.0.get_vote_credits(&id).await;
+            match res {
+                Ok(breakdown) => lua.to_value(&breakdown),
+                Err(e) => Err(LuaError::external(e.to_string())),
+            }
+        });
+
+        /*
+	/// Redeems all unredeemed votes of an entity for vote credits
+	pub async fn redeem_vote_credits(&self, id: &str) -> Result<VoteCreditRedeemLog, crate::Error> { */
+        methods.add_scheduler_async_method("RedeemVoteCredits", async |lua, this, (actor_id, id): (String, String)| {
+            this.require_perm(&actor_id, REDEEM_VOTE_CREDITS_PERM).await?;
+            let res = this.0.redeem_vote_credits(&id).await;
+            match res {
+                Ok(log) => lua.to_value(&log),
+                Err(e) => Err(LuaError::external(e.to_string())),
+            }
+        });
+
+        /*
+	/// Returns all vote credit redemptions of an entity, newest first
+	pub async fn fetch_vote_credit_redeem_logs(&self, id: &str) -> Result<Vec<VoteCreditRedeemLog>, crate::Error> { */
+        methods.add_scheduler_async_method("FetchVoteCreditRedeemLogs", async |lua, this, id: String| {
+            let res = this.0.fetch_vote_credit_redeem_logs(&id).await;
+            match res {
+                Ok(logs) => lua.to_value(&logs),
+                Err(e) => Err(LuaError::external(e.to_string())),
+            }
+        });
+
+        /*
+	/// Returns the entities of this type with the most votes, using entity_approx_votes
+	pub async fn leaderboard(&self, limit: i64, offset: i64) -> Result<Vec<LeaderboardEntry<E::SummaryObject>>, crate::Error> { */
+        methods.add_scheduler_async_method("Leaderboard", async |lua, this, (limit, offset): (i64, Option<i64>)| {
+            let res = this.0.leaderboard(limit, offset.unwrap_or(0)).await;
+            match res {
+                Ok(entries) => lua.to_value(&entries),
+                Err(e) => Err(LuaError::external(e.to_string())),
+            }
+        });
+
+        /*
+	/// Recomputes entity_approx_votes from entity_votes for a single entity, or every entity of this type if id is None
+	pub async fn reconcile_votes(&self, id: Option<&str>, batch_size: i64) -> Result<Vec<VoteCountChange>, crate::Error> { */
+        methods.add_scheduler_async_method("ReconcileVotes", async |lua, this, (id, batch_size): (Option<String>, Option<i64>)| {
+            let res = this.0.reconcile_votes(id.as_deref(), batch_size.unwrap_or(DEFAULT_RECONCILE_BATCH_SIZE)).await;
+            match res {
+                Ok(changes) => lua.to_value(&changes),
+                Err(e) => Err(LuaError::external(e.to_string())),
+            }
+        });
+
+        /*
+	/// Returns the archived vote counts of an entity from previous vote resets, newest first
+	pub async fn fetch_vote_reset_history(&self, id: &str) -> Result<Vec<VoteResetSnapshot>, crate::Error> { */
+        methods.add_scheduler_async_method("FetchVoteResetHistory", async |lua, this, id: String| {
+            let res = this.0.fetch_vote_reset_history(&id).await;
+            match res {
+                Ok(history) => lua.to_value(&history),
+                Err(e) => Err(LuaError::external(e.to_string())),
+            }
+        });
+
+        /*
+	/// Returns the amount of upvotes and downvotes an entity received in each bucket of the range [from, to)
+	pub async fn vote_time_series(&self, id: &str, bucket: VoteBucket, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<VoteTimeSeriesPoint>, crate::Error> { */
+        methods.add_scheduler_async_method("VoteTimeSeries", async |lua, this, (id, bucket, from, to): (String, String, LuaUserDataRef<LuaDateTime<chrono_tz::Tz>>, LuaUserDataRef<LuaDateTime<chrono_tz::Tz>>)| {
+            let bucket: VoteBucket = bucket.parse().map_err(LuaError::external)?;
+            let from = from.dt.with_timezone(&Utc);
+            let to = to.dt.with_timezone(&Utc);
+
+            let res = this.0.vote_time_series(&id, bucket, from, to).await;
+            match res {
+                Ok(points) => lua.to_value(&points),
+                Err(e) => Err(LuaError::external(e.to_string())),
+            }
+        });
+
+        /*
+	/// Returns the flags of an entity, merging the flags of the entity type with the overrides stored in entity_flags
+	pub async fn flags(&self, id: &str) -> Result<EntityFlags, crate::Error> { */
+        methods.add_scheduler_async_method("Flags", async |_lua, this, id: String| {
+            let flags = this.0.flags(&id).await.map_err(|e| LuaError::external(e.to_string()))?;
+            Ok(flag_names(flags))
+        });
+
+        /*
+	/// Sets the timezone used by weekend vote rules for an entity, None falls back to the timezone of the rules
+	pub async fn set_timezone(&self, id: &str, timezone: Option<chrono_tz::Tz>) -> Result<(), crate::Error> { */
+        methods.add_scheduler_async_method("SetTimezone", async |_lua, this, (id, timezone): (String, Option<String>)| {
+            let timezone = match timezone {
+                Some(tz) => Some(tz.parse::<chrono_tz::Tz>().map_err(|e| LuaError::external(format!("Invalid timezone: {e}")))?),
+                None => None,
+            };
+            this.0.set_timezone(&id, timezone).await.map_err(|e| LuaError::external(e.to_string()))
+        });
+
+        /*
+	/// Returns the flag changes made to an entity, newest first
+	pub async fn fetch_flag_logs(&self, id: &str) -> Result<Vec<EntityFlagLog>, crate::Error> { */
+        methods.add_scheduler_async_method("FetchFlagLogs", async |lua, this, id: String| {
+            let res = this.0.fetch_flag_logs(&id).await;
+            match res {
+                Ok(logs) => lua.to_value(&logs),
+                Err(e) => Err(LuaError::external(e.to_string())),
+            }
+        });
+
+        /*
+	/// Creates a new entity, registering it in known_entities in the same transaction
+	pub async fn create(&self, obj: E::CreateObject) -> Result<String, crate::Error> { */
+        methods.add_scheduler_async_method("Create", async |lua, this, (actor_id, obj): (String, LuaValue)| {
+            this.require_perm(&actor_id, CREATE_ENTITY_PERM).await?;
+            let obj: T::CreateObject = lua.from_value(obj)?;
+            this.0.create(obj).await.map_err(|e| LuaError::external(e.to_string()))
+        });
+
+        /*
+	/// Deletes an entity along with its votes, sessions, webhooks, known_entities row and CDN assets
+	pub async fn delete(&self, id: &str) -> Result<(), crate::Error> { */
+        methods.add_scheduler_async_method("Delete", async |_lua, this, (actor_id, id): (String, String)| {
+            this.require_perm(&actor_id, DELETE_ENTITY_PERM).await?;
+            this.0.delete(&id).await.map_err(|e| LuaError::external(e.to_string()))
+        });
+
+        /*
+	/// Voids all mutable, non-void votes matching the filter on behalf of a staff member, returning the votes that were voided
+	pub async fn void_votes(&self, filter: VoidVotesFilter, reason: &str, actor_id: &str) -> Result<Vec<EntityVote>, crate::Error> { */
+        methods.add_scheduler_async_method("VoidVotes", async |lua, this, (actor_id, filter, reason): (String, VoidVotesFilter, String)| {
+            this.require_perm(&actor_id, VOID_VOTES_PERM).await?;
+            let res = this.0.void_votes(filter, &reason, &actor_id).await;
+            match res {
+                Ok(votes) => lua.to_value(&votes),
+                Err(e) => Err(LuaError::external(e.to_string())),
+            }
+        });
     }
 }
 
@@ -125,15 +368,10 @@ impl<T: Entity> LuaUserData for LuaEntity<T> {
             Ok(this.0.cdn_folder().to_string())
         });
 
+        // Goes through EntityManager so the overrides stored in entity_flags are applied
         methods.add_scheduler_async_method("Flags", async |_, this, id: String| {
-            let mut flags = Vec::new();
-            let fset = this.0.flags(&id).await.map_err(|e| LuaError::external(e.to_string()))?;
-            for flag in EntityFlags::FLAGS {
-                if fset.contains(*flag.value()) {
-                    flags.push(flag.name().to_string());
-                }
-            }
-            Ok(flags)
+            let flags = EntityManager::new(this.0.clone()).flags(&id).await.map_err(|e| LuaError::external(e.to_string()))?;
+            Ok(flag_names(flags))
         });
 
         methods.add_scheduler_async_method("GetInfo", async |lua, this, id: String| {
@@ -160,8 +398,9 @@ impl<T: Entity> LuaUserData for LuaEntity<T> {
             }
         });
 
+        // GetPublic, GetSummary and Update go through EntityManager so the entity cache is used and invalidated
         methods.add_scheduler_async_method("GetPublic", async |lua, this, id: String| {
-            let res = this.0.get_public(&id).await;
+            let res = EntityManager::new(this.0.clone()).get_public(&id).await;
             match res {
                 Ok(public_obj) => lua.to_value(&public_obj),
                 Err(e) => Err(LuaError::external(e.to_string())),
@@ -169,11 +408,32 @@ impl<T: Entity> LuaUserData for LuaEntity<T> {
         });
 
         methods.add_scheduler_async_method("GetSummary", async |lua, this, id: String| {
-            let res = this.0.get_summary(&id).await;
+            let res = EntityManager::new(this.0.clone()).get_summary(&id).await;
             match res {
                 Ok(summary_obj) => lua.to_value(&summary_obj),
                 Err(e) => Err(LuaError::external(e.to_string())),
             }
         });
+
+        methods.add_scheduler_async_method("Update", async |lua, this, (id, obj): (String, LuaValue)| {
+            let obj: T::UpdateObject = lua.from_value(obj)?;
+            EntityManager::new(this.0.clone())
+                .update(&id, obj)
+                .await
+                .map_err(|e| LuaError::external(e.to_string()))
+        });
+
+        methods.add_scheduler_async_method("List", async |lua, this, query: Option<LuaValue>| {
+            let query: EntityListQuery<T::ListFilter> = match query {
+                Some(query) => lua.from_value(query)?,
+                None => EntityListQuery::default(),
+            };
+
+            let res = this.0.list(query).await;
+            match res {
+                Ok(page) => lua.to_value(&page),
+                Err(e) => Err(LuaError::external(e.to_string())),
+            }
+        });
     }
 }
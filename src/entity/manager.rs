@@ -1,4 +1,6 @@
-use crate::{entity::{Entity, EntityFlags}, types::votes::{EntityVote, UserVote, VoteInfo}};
+use std::collections::BTreeMap;
+
+use crate::{entity::{Entity, EntityFlags, cache::{CacheView, ENTITY_CACHE}, events::{VoteCast, publish_vote}, flags::{ChangeFlagsError, EntityFlagLog, FlagAction, FlagOverrides}, webhooks::{WebhookDispatcher, WebhookEvent}}, utils::asset::{AssetType, delete_asset}, types::votes::{CastVoteOutcome, EntityVote, LeaderboardEntry, UserVote, VoteCountChange, VoteBucket, VoteReset, VoteReminder, VoteResetSnapshot, VoteTimeSeriesError, VoteTimeSeriesPoint, VoidVotesFilter, VoteCreditBreakdown, VoteCreditRedeemLog, VoteCreditTier, VoteInfo, VoteWait}};
 use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl};
 use diesel_async::RunQueryDsl;
 
@@ -19,6 +21,74 @@ diesel::table! {
     }
 }
 
+/// Columns needed to load an EntityVote using sqlx
+/// 
+/// created_at is cast as older databases store it without a timezone
+pub(crate) const ENTITY_VOTE_COLS: &str = "itag, target_id, target_type, author, upvote, void, void_reason, voided_at, created_at::TIMESTAMPTZ AS created_at, vote_num, immutable";
+
+/// The maximum amount of entries that can be fetched from a leaderboard at once
+pub const MAX_LEADERBOARD_LIMIT: i64 = 100;
+/// The maximum amount of buckets a vote time series can span
+pub const MAX_VOTE_TIME_SERIES_BUCKETS: i64 = 1000;
+
+/// The default amount of entities reconciled per transaction
+pub const DEFAULT_RECONCILE_BATCH_SIZE: i64 = 500;
+
+/// The default amount of entities fetched per batch of a vote reset, each entity is still reset in its own transaction
+pub const DEFAULT_RESET_BATCH_SIZE: i64 = 100;
+
+/// Valid votes (aliased as v) cast since the last vote reset of their entity
+/// 
+/// Immutable votes survive a reset so they must be excluded explicitly
+const VALID_VOTES_SINCE_RESET: &str = "v.void = false AND v.created_at::TIMESTAMPTZ > COALESCE(
+	(SELECT MAX(h.created_at) FROM entity_vote_reset_history h WHERE h.target_id = v.target_id AND h.target_type = v.target_type),
+	'-infinity'
+)";
+
+/// Void reason used for votes voided by a vote reset
+pub const VOTE_RESET_REASON: &str = "vote reset";
+
+/// Votes that can be redeemed for vote credits
+const REDEEMABLE_VOTES_FILTER: &str = "target_id = $1 AND target_type = $2 AND void = false AND upvote = true AND credit_redeem IS NULL";
+
+/// Tables keyed by (target_id, target_type) which are cleaned up when an entity is deleted
+/// 
+/// Not every deployment has all of these tables (api_sessions predates migrations), so
+/// tables missing from the database are skipped. known_entities must come last as other
+/// tables may reference it
+const ENTITY_OWNED_TABLES: [&str; 9] = [
+	"entity_votes",
+	"entity_flags",
+	"entity_vote_reminders",
+	"entity_approx_votes",
+	"entity_vote_redeem_logs",
+	"webhook_logs",
+	"webhooks",
+	"api_sessions",
+	"known_entities",
+];
+
+/// Returns how long a user has to wait to vote again given the time of their last vote
+/// 
+/// Returns None if the user can vote again
+fn cooldown_wait(last_vote: chrono::DateTime<chrono::Utc>, vote_time: u16) -> Option<VoteWait> {
+	let time_to_wait = chrono::Duration::hours(vote_time as i64) - (chrono::Utc::now() - last_vote);
+
+	if time_to_wait <= chrono::Duration::zero() {
+		return None;
+	}
+
+	let hours = time_to_wait.num_hours();
+	let minutes = time_to_wait.num_minutes() - (hours * 60);
+	let seconds = time_to_wait.num_seconds() - (hours * 3600 + minutes * 60);
+
+	Some(VoteWait {
+		hours: hours as i32,
+		minutes: minutes as i32,
+		seconds: seconds as i32,
+	})
+}
+
 pub struct EntityManager<E: Entity> {
     entity: E
 }
@@ -34,6 +104,173 @@ impl<E: Entity> EntityManager<E> {
         &self.entity
     }
 
+	/// Returns the public object of an entity, using the entity cache if possible
+	pub async fn get_public(&self, id: &str) -> Result<E::PublicObject, crate::Error> {
+		if let Some(obj) = ENTITY_CACHE.get(self.entity.target_type(), id, CacheView::Public) {
+			return Ok(obj);
+		}
+
+		let generation = ENTITY_CACHE.generation();
+		let obj = self.entity.get_public(id).await?;
+		ENTITY_CACHE.insert(self.entity.target_type(), id, CacheView::Public, generation, &obj);
+		Ok(obj)
+	}
+
+	/// Returns the summary object of an entity, using the entity cache if possible
+	pub async fn get_summary(&self, id: &str) -> Result<E::SummaryObject, crate::Error> {
+		if let Some(obj) = ENTITY_CACHE.get(self.entity.target_type(), id, CacheView::Summary) {
+			return Ok(obj);
+		}
+
+		let generation = ENTITY_CACHE.generation();
+		let obj = self.entity.get_summary(id).await?;
+		ENTITY_CACHE.insert(self.entity.target_type(), id, CacheView::Summary, generation, &obj);
+		Ok(obj)
+	}
+
+	/// Updates an entity, invalidating its cached objects
+	pub async fn update(&self, id: &str, obj: E::UpdateObject) -> Result<(), crate::Error> {
+		let Some(id) = self.entity.resolve_id(id).await? else {
+			return Err(format!("{} not found", self.entity.name()).into());
+		};
+
+		let res = self.entity.update(&id, obj).await;
+		self.invalidate_cache(&id);
+		res
+	}
+
+	/// Sets the timezone used by weekend vote rules for an entity, None falls back to the timezone of the rules
+	pub async fn set_timezone(&self, id: &str, timezone: Option<chrono_tz::Tz>) -> Result<(), crate::Error> {
+		let Some(id) = self.entity.resolve_id(id).await? else {
+			return Err(format!("{} not found", self.entity.name()).into());
+		};
+
+		sqlx::query(
+			"INSERT INTO known_entities (target_id, target_type, timezone) VALUES ($1, $2, $3)
+			ON CONFLICT (target_id, target_type) DO UPDATE SET timezone = EXCLUDED.timezone",
+		)
+		.bind(&id)
+		.bind(self.entity.target_type())
+		.bind(timezone.map(|tz| tz.name().to_string()))
+		.execute(self.entity.pool())
+		.await?;
+
+		Ok(())
+	}
+
+	/// Drops the cached objects of an entity
+	pub fn invalidate_cache(&self, id: &str) {
+		ENTITY_CACHE.invalidate(self.entity.target_type(), id);
+	}
+
+	/// Returns the flag overrides stored for an entity
+	pub async fn flag_overrides(&self, id: &str) -> Result<FlagOverrides, crate::Error> {
+		let row: Option<(i64, i64)> = sqlx::query_as(
+			"SELECT flags_set, flags_cleared FROM entity_flags WHERE target_id = $1 AND target_type = $2",
+		)
+		.bind(id)
+		.bind(self.entity.target_type())
+		.fetch_optional(self.entity.pool())
+		.await?;
+
+		Ok(row.map(|(set, cleared)| FlagOverrides::from_bits(set, cleared)).unwrap_or_default())
+	}
+
+	/// Returns the flags of an entity, merging the flags of the entity type with the overrides stored in entity_flags
+	pub async fn flags(&self, id: &str) -> Result<EntityFlags, crate::Error> {
+		let defaults = self.entity.flags(id).await?;
+		let overrides = self.flag_overrides(id).await?;
+		Ok(overrides.resolve(defaults))
+	}
+
+	/// Forces the given flags on for an entity, logging the change
+	/// 
+	/// This does not check permissions, use SharedLayer::change_entity_flags for staff-gated changes
+	pub async fn set_flags(&self, id: &str, flags: EntityFlags, actor_id: &str, reason: &str) -> Result<EntityFlags, crate::Error> {
+		self.change_flags(id, FlagAction::Set, flags, actor_id, reason).await
+	}
+
+	/// Forces the given flags off for an entity, logging the change
+	/// 
+	/// This does not check permissions, use SharedLayer::change_entity_flags for staff-gated changes
+	pub async fn clear_flags(&self, id: &str, flags: EntityFlags, actor_id: &str, reason: &str) -> Result<EntityFlags, crate::Error> {
+		self.change_flags(id, FlagAction::Clear, flags, actor_id, reason).await
+	}
+
+	/// Applies a flag change and logs it in the same transaction, returning the new resolved flags
+	async fn change_flags(&self, id: &str, action: FlagAction, flags: EntityFlags, actor_id: &str, reason: &str) -> Result<EntityFlags, crate::Error> {
+		if reason.trim().is_empty() {
+			return Err(ChangeFlagsError::MissingReason.into());
+		}
+
+		if self.entity.get_info(id).await?.is_none() {
+			return Err(ChangeFlagsError::EntityNotFound(self.entity.name().to_string(), id.to_string()).into());
+		}
+
+		let defaults = self.entity.flags(id).await?;
+
+		let mut tx = self.entity.pool().begin().await?;
+
+		let row: Option<(i64, i64)> = sqlx::query_as(
+			"SELECT flags_set, flags_cleared FROM entity_flags WHERE target_id = $1 AND target_type = $2 FOR UPDATE",
+		)
+		.bind(id)
+		.bind(self.entity.target_type())
+		.fetch_optional(&mut *tx)
+		.await?;
+
+		let old = row.map(|(set, cleared)| FlagOverrides::from_bits(set, cleared)).unwrap_or_default();
+		let new = old.apply(action, flags);
+
+		sqlx::query(
+			"INSERT INTO entity_flags (target_id, target_type, flags_set, flags_cleared) VALUES ($1, $2, $3, $4)
+			ON CONFLICT (target_id, target_type) DO UPDATE SET flags_set = EXCLUDED.flags_set, flags_cleared = EXCLUDED.flags_cleared, updated_at = NOW()",
+		)
+		.bind(id)
+		.bind(self.entity.target_type())
+		.bind(new.set.bits() as i64)
+		.bind(new.cleared.bits() as i64)
+		.execute(&mut *tx)
+		.await?;
+
+		let new_flags = new.resolve(defaults);
+
+		sqlx::query(
+			"INSERT INTO entity_flag_logs (target_id, target_type, actor_id, action, flags, old_flags, new_flags, reason)
+			VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+		)
+		.bind(id)
+		.bind(self.entity.target_type())
+		.bind(actor_id)
+		.bind(action.as_str())
+		.bind(flags.bits() as i64)
+		.bind(old.resolve(defaults).bits() as i64)
+		.bind(new_flags.bits() as i64)
+		.bind(reason)
+		.execute(&mut *tx)
+		.await?;
+
+		tx.commit().await?;
+
+		self.invalidate_cache(id);
+
+		Ok(new_flags)
+	}
+
+	/// Returns the flag changes made to an entity, newest first
+	pub async fn fetch_flag_logs(&self, id: &str) -> Result<Vec<EntityFlagLog>, crate::Error> {
+		let logs: Vec<EntityFlagLog> = sqlx::query_as(
+			"SELECT id, target_id, target_type, actor_id, action, flags, old_flags, new_flags, reason, created_at FROM entity_flag_logs
+			WHERE target_id = $1 AND target_type = $2 ORDER BY created_at DESC",
+		)
+		.bind(id)
+		.bind(self.entity.target_type())
+		.fetch_all(self.entity.pool())
+		.await?;
+
+		Ok(logs)
+	}
+
 	/// Fetches all votes for a given user and entity.
 	/// 
 	/// This always returns in created_at descending order (i.e. newest votes first).
@@ -84,7 +321,7 @@ impl<E: Entity> EntityManager<E> {
 	/// Helper method to get full vote info for an entity, wrapping the underlying entity's get_vote_info method and adding flag info.
 	pub async fn get_full_vote_info(&self, id: &str, user_id: Option<&str>) -> Result<VoteInfo, crate::Error> {
 		let vi = self.entity.get_vote_info(id, user_id).await?;
-		let flags = self.entity.flags(id).await?;
+		let flags = self.flags(id).await?;
 
 		Ok(VoteInfo {
 			per_user: vi.per_user,
@@ -109,21 +346,8 @@ impl<E: Entity> EntityManager<E> {
 		if vi.multiple_votes {
 			if let Some(last_vote) = valid_votes.iter().next() {
 				// Check if the user has voted in the last vote time
-				has_voted = last_vote.created_at + chrono::Duration::hours(vi.vote_time as i64) > chrono::Utc::now();
-
-				if has_voted {
-					let time_elapsed = chrono::Utc::now() - last_vote.created_at;
-					let time_to_wait = chrono::Duration::hours(vi.vote_time as i64) - time_elapsed;
-					let hours = time_to_wait.num_hours();
-					let minutes = time_to_wait.num_minutes() - (hours * 60);
-					let seconds = time_to_wait.num_seconds() - (hours * 3600 + minutes * 60);
-
-					vote_wait = Some(crate::types::votes::VoteWait {
-						hours: hours as i32,
-						minutes: minutes as i32,
-						seconds: seconds as i32,
-					});
-				}
+				vote_wait = cooldown_wait(last_vote.created_at, vi.vote_time);
+				has_voted = vote_wait.is_some();
 			}
 		} else {
 			// Case 2: Single vote entity
@@ -167,13 +391,130 @@ impl<E: Entity> EntityManager<E> {
 	}
 
 	/// Helper function to give votes to an entity 
+	/// 
+	/// This does not check if the user is allowed to vote, use cast_vote for that
 	pub async fn give_votes(&self, id: &str, user_id: &str, upvote: bool) -> Result<(), crate::Error> {
 		let vi = self.get_full_vote_info(id, Some(user_id)).await?;
 		
 		let mut tx = self.entity.pool().begin().await?;
 
+		self.insert_votes(&mut *tx, id, user_id, upvote, &vi).await?;
+
+		tx.commit().await?;
+
+		self.invalidate_cache(id);
+		self.publish_vote_event(id, user_id, upvote, vi.per_user);
+
+		let flags = self.flags(id).await?;
+		self.send_vote_webhooks(flags, id, user_id, upvote, vi.per_user).await;
+
+		Ok(())
+	}
+
+	/// Checks if a user is allowed to vote on an entity and if so, gives the votes
+	/// 
+	/// The cooldown check and the vote inserts happen under a per-user/entity advisory lock
+	/// so concurrent requests cannot double vote
+	pub async fn cast_vote(&self, id: &str, user_id: &str, upvote: bool) -> Result<CastVoteOutcome, crate::Error> {
+		let vi = self.get_full_vote_info(id, Some(user_id)).await?;
+		let flags = self.flags(id).await?;
+
+		if flags.contains(EntityFlags::BANNED) {
+			return Ok(CastVoteOutcome::Banned);
+		}
+
+		if !flags.contains(EntityFlags::SUPPORTS_VOTING)
+			|| (upvote && !vi.supports_upvotes)
+			|| (!upvote && !vi.supports_downvotes)
+		{
+			return Ok(CastVoteOutcome::NotSupported);
+		}
+
+		let mut tx = self.entity.pool().begin().await?;
+
+		// Released automatically on commit/rollback
+		sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))")
+			.bind(format!("vote:{}:{}:{}", self.entity.target_type(), id, user_id))
+			.execute(&mut *tx)
+			.await?;
+
+		// Votes from before the last reset (e.g. credit-redeemed votes, which resets leave alone) don't count
+		let last_vote: Option<chrono::DateTime<chrono::Utc>> = sqlx::query_scalar(&format!(
+			"SELECT v.created_at::TIMESTAMPTZ FROM entity_votes v WHERE v.author = $1 AND v.target_id = $2 AND v.target_type = $3 AND {VALID_VOTES_SINCE_RESET} ORDER BY v.created_at DESC LIMIT 1",
+		))
+		.bind(user_id)
+		.bind(id)
+		.bind(self.entity.target_type())
+		.fetch_optional(&mut *tx)
+		.await?;
+
+		if let Some(last_vote) = last_vote {
+			if !vi.multiple_votes {
+				// Single vote entities can only ever be voted on once
+				return Ok(CastVoteOutcome::AlreadyVoted);
+			}
+
+			if let Some(wait) = cooldown_wait(last_vote, vi.vote_time) {
+				return Ok(CastVoteOutcome::OnCooldown { wait });
+			}
+		}
+
+		self.insert_votes(&mut *tx, id, user_id, upvote, &vi).await?;
+
+		tx.commit().await?;
+
+		self.invalidate_cache(id);
+		self.publish_vote_event(id, user_id, upvote, vi.per_user);
+
+		self.send_vote_webhooks(flags, id, user_id, upvote, vi.per_user).await;
+
+		Ok(CastVoteOutcome::Voted { votes: vi.per_user })
+	}
+
+	/// Publishes a VoteCast event for votes that have just been committed
+	fn publish_vote_event(&self, id: &str, user_id: &str, upvote: bool, votes: u8) {
+		publish_vote(VoteCast {
+			target_id: id.to_string(),
+			target_type: self.entity.target_type().to_string(),
+			user_id: user_id.to_string(),
+			votes,
+			upvote,
+			created_at: chrono::Utc::now(),
+		});
+	}
+
+	/// Queues NewVote webhooks for an entity if it supports webhooks
+	/// 
+	/// The vote has already been committed at this point so failures are only logged
+	async fn send_vote_webhooks(&self, flags: EntityFlags, id: &str, user_id: &str, upvote: bool, votes: u8) {
+		if !flags.contains(EntityFlags::SUPPORTS_WEBHOOKS) {
+			return;
+		}
+
+		let event = WebhookEvent::NewVote {
+			user_id: user_id.to_string(),
+			upvote,
+			votes,
+		};
+
+		if let Err(e) = WebhookDispatcher::new(self.entity.pool().clone()).dispatch(id, self.entity.target_type(), event).await {
+			log::error!("Failed to dispatch vote webhooks for {} {id}: {e}", self.entity.target_type());
+		}
+	}
+
+	/// Inserts per_user votes, updates entity_approx_votes and schedules the user's vote reminder on the given connection
+	async fn insert_votes(
+		&self,
+		conn: &mut sqlx::PgConnection,
+		id: &str,
+		user_id: &str,
+		upvote: bool,
+		vi: &VoteInfo,
+	) -> Result<(), crate::Error> {
+		let per_user = vi.per_user;
+
 		// Keep adding votes until, but not including vote_info.per_user
-		for i in 0..vi.per_user {
+		for i in 0..per_user {
 			sqlx::query(
 				"INSERT INTO entity_votes (author, target_id, target_type, upvote, vote_num) VALUES ($1, $2, $3, $4, $5)",
 			)
@@ -182,7 +523,7 @@ impl<E: Entity> EntityManager<E> {
 			.bind(self.entity.target_type())
 			.bind(upvote)
 			.bind(i as i32)
-			.execute(&mut *tx)
+			.execute(&mut *conn)
 			.await?;
 		}
 
@@ -193,12 +534,601 @@ impl<E: Entity> EntityManager<E> {
 		)
 		.bind(id)
 		.bind(self.entity.target_type())
-		.bind(if upvote { vi.per_user as i64 } else { -(vi.per_user as i64) })
+		.bind(if upvote { per_user as i64 } else { -(per_user as i64) })
+		.execute(&mut *conn)
+		.await?;
+
+		// Only does anything if the user has opted in to reminders for this entity
+		sqlx::query(
+			"UPDATE entity_vote_reminders SET due_at = NOW() + make_interval(hours => $1) WHERE user_id = $2 AND target_id = $3 AND target_type = $4",
+		)
+		.bind(vi.vote_time as i32)
+		.bind(user_id)
+		.bind(id)
+		.bind(self.entity.target_type())
+		.execute(&mut *conn)
+		.await?;
+
+		Ok(())
+	}
+
+	/// Returns when a user can next vote on an entity, None if the user can vote now or the entity only supports a single vote
+	pub async fn next_vote_at(&self, id: &str, user_id: &str) -> Result<Option<chrono::DateTime<chrono::Utc>>, crate::Error> {
+		let vi = self.get_full_vote_info(id, Some(user_id)).await?;
+
+		if !vi.multiple_votes {
+			return Ok(None);
+		}
+
+		// Votes from before the last reset (e.g. credit-redeemed votes, which resets leave alone) don't count
+		let last_vote: Option<chrono::DateTime<chrono::Utc>> = sqlx::query_scalar(&format!(
+			"SELECT v.created_at::TIMESTAMPTZ FROM entity_votes v WHERE v.author = $1 AND v.target_id = $2 AND v.target_type = $3 AND {VALID_VOTES_SINCE_RESET} ORDER BY v.created_at DESC LIMIT 1",
+		))
+		.bind(user_id)
+		.bind(id)
+		.bind(self.entity.target_type())
+		.fetch_optional(self.entity.pool())
+		.await?;
+
+		let next = last_vote
+			.map(|last_vote| last_vote + chrono::Duration::hours(vi.vote_time as i64))
+			.filter(|next| *next > chrono::Utc::now());
+
+		Ok(next)
+	}
+
+	/// Opts a user in to being reminded when they can vote on an entity again
+	/// 
+	/// If the user is still on cooldown, the reminder is due at the end of the cooldown. Otherwise it
+	/// becomes due after their next vote
+	pub async fn enable_vote_reminder(&self, id: &str, user_id: &str) -> Result<VoteReminder, crate::Error> {
+		let flags = self.flags(id).await?;
+		if !flags.contains(EntityFlags::SUPPORTS_VOTING) || !flags.contains(EntityFlags::SUPPORTS_MULTIPLE_VOTES) {
+			return Err(format!("{} does not support vote reminders", self.entity.name()).into());
+		}
+
+		let due_at = self.next_vote_at(id, user_id).await?;
+
+		let reminder: VoteReminder = sqlx::query_as(
+			"INSERT INTO entity_vote_reminders (user_id, target_id, target_type, due_at) VALUES ($1, $2, $3, $4)
+			ON CONFLICT (user_id, target_id, target_type) DO UPDATE SET due_at = EXCLUDED.due_at
+			RETURNING user_id, target_id, target_type, due_at, last_fired_at, created_at",
+		)
+		.bind(user_id)
+		.bind(id)
+		.bind(self.entity.target_type())
+		.bind(due_at)
+		.fetch_one(self.entity.pool())
+		.await?;
+
+		Ok(reminder)
+	}
+
+	/// Opts a user out of vote reminders for an entity, returning whether or not the user was opted in
+	pub async fn disable_vote_reminder(&self, id: &str, user_id: &str) -> Result<bool, crate::Error> {
+		let res = sqlx::query("DELETE FROM entity_vote_reminders WHERE user_id = $1 AND target_id = $2 AND target_type = $3")
+			.bind(user_id)
+			.bind(id)
+			.bind(self.entity.target_type())
+			.execute(self.entity.pool())
+			.await?;
+
+		Ok(res.rows_affected() > 0)
+	}
+
+	/// Voids all mutable, non-void votes matching the filter on behalf of a staff member, returning the votes that were voided
+	/// 
+	/// entity_approx_votes is adjusted in the same transaction
+	pub async fn void_votes(&self, filter: VoidVotesFilter, reason: &str, actor_id: &str) -> Result<Vec<EntityVote>, crate::Error> {
+		let mut qb = sqlx::QueryBuilder::<sqlx::Postgres>::new("UPDATE entity_votes SET void = true, voided_at = NOW(), void_reason = ");
+		qb.push_bind(reason.to_string());
+		qb.push(", voided_by = ");
+		qb.push_bind(actor_id.to_string());
+		qb.push(" WHERE void = false AND immutable = false AND target_type = ");
+		qb.push_bind(self.entity.target_type());
+
+		match filter {
+			VoidVotesFilter::Itag { itag } => {
+				qb.push(" AND itag = ").push_bind(itag);
+			}
+			VoidVotesFilter::User { user_id, target_id } => {
+				qb.push(" AND author = ").push_bind(user_id);
+				if let Some(target_id) = target_id {
+					qb.push(" AND target_id = ").push_bind(target_id);
+				}
+			}
+			VoidVotesFilter::Entity { target_id, from, to } => {
+				qb.push(" AND target_id = ").push_bind(target_id);
+				if let Some(from) = from {
+					qb.push(" AND created_at >= ").push_bind(from);
+				}
+				if let Some(to) = to {
+					qb.push(" AND created_at < ").push_bind(to);
+				}
+			}
+		}
+
+		qb.push(" RETURNING ");
+		qb.push(ENTITY_VOTE_COLS);
+
+		let mut tx = self.entity.pool().begin().await?;
+
+		let voided: Vec<EntityVote> = qb.build_query_as().fetch_all(&mut *tx).await?;
+
+		// Undo the effect of the voided votes on the approximate count of each affected entity
+		//
+		// BTreeMap keeps the update order stable to avoid deadlocks between concurrent voids
+		let mut deltas: BTreeMap<&str, i64> = BTreeMap::new();
+		for vote in voided.iter() {
+			*deltas.entry(vote.target_id.as_str()).or_default() += if vote.upvote { 1 } else { -1 };
+		}
+
+		for (target_id, delta) in deltas {
+			sqlx::query(
+				"UPDATE entity_approx_votes SET approximate_votes = approximate_votes - $1 WHERE target_id = $2 AND target_type = $3",
+			)
+			.bind(delta)
+			.bind(target_id)
+			.bind(self.entity.target_type())
+			.execute(&mut *tx)
+			.await?;
+		}
+
+		tx.commit().await?;
+
+		for vote in voided.iter() {
+			self.invalidate_cache(&vote.target_id);
+		}
+
+		Ok(voided)
+	}
+
+	/// Returns the vote credit tiers for this entity type, sorted by position
+	pub async fn get_vote_credit_tiers(&self) -> Result<Vec<VoteCreditTier>, crate::Error> {
+		let tiers: Vec<VoteCreditTier> = sqlx::query_as(
+			"SELECT id, target_type, position, votes, cents, created_at FROM vote_credit_tiers WHERE target_type = $1 ORDER BY position ASC",
+		)
+		.bind(self.entity.target_type())
+		.fetch_all(self.entity.pool())
+		.await?;
+
+		Ok(tiers)
+	}
+
+	/// Returns the credits an entity would earn by redeeming its votes right now
+	pub async fn get_vote_credits(&self, id: &str) -> Result<VoteCreditBreakdown, crate::Error> {
+		let votes: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM entity_votes WHERE {REDEEMABLE_VOTES_FILTER}"))
+			.bind(id)
+			.bind(self.entity.target_type())
+			.fetch_one(self.entity.pool())
+			.await?;
+
+		let tiers = self.get_vote_credit_tiers().await?;
+
+		Ok(VoteCreditBreakdown::calculate(&tiers, votes))
+	}
+
+	/// Redeems all unredeemed votes of an entity for vote credits
+	/// 
+	/// Redeemed votes are stamped with the ID of the redeem log and become immutable
+	pub async fn redeem_vote_credits(&self, id: &str) -> Result<VoteCreditRedeemLog, crate::Error> {
+		let flags = self.flags(id).await?;
+
+		if !flags.contains(EntityFlags::SUPPORTS_VOTE_CREDITS) {
+			return Err(format!("{} does not support vote credits", self.entity.name()).into());
+		}
+
+		let tiers = self.get_vote_credit_tiers().await?;
+
+		let mut tx = self.entity.pool().begin().await?;
+
+		// Lock the votes being redeemed so they cannot be voided or redeemed twice concurrently
+		let itags: Vec<uuid::Uuid> = sqlx::query_scalar(&format!("SELECT itag FROM entity_votes WHERE {REDEEMABLE_VOTES_FILTER} FOR UPDATE"))
+			.bind(id)
+			.bind(self.entity.target_type())
+			.fetch_all(&mut *tx)
+			.await?;
+
+		if itags.is_empty() {
+			return Err("No votes to redeem".into());
+		}
+
+		let breakdown = VoteCreditBreakdown::calculate(&tiers, itags.len() as i64);
+
+		let log: VoteCreditRedeemLog = sqlx::query_as(
+			"INSERT INTO entity_vote_redeem_logs (target_id, target_type, credits) VALUES ($1, $2, $3)
+			RETURNING id, target_id, target_type, credits, redeemed_credits, redeemed_at, created_at",
+		)
+		.bind(id)
+		.bind(self.entity.target_type())
+		.bind(breakdown.credits)
+		.fetch_one(&mut *tx)
+		.await?;
+
+		sqlx::query("UPDATE entity_votes SET credit_redeem = $1, immutable = true WHERE itag = ANY($2)")
+			.bind(log.id)
+			.bind(&itags)
+			.execute(&mut *tx)
+			.await?;
+
+		tx.commit().await?;
+
+		Ok(log)
+	}
+
+	/// Returns all vote credit redemptions of an entity, newest first
+	pub async fn fetch_vote_credit_redeem_logs(&self, id: &str) -> Result<Vec<VoteCreditRedeemLog>, crate::Error> {
+		let logs: Vec<VoteCreditRedeemLog> = sqlx::query_as(
+			"SELECT id, target_id, target_type, credits, redeemed_credits, redeemed_at, created_at FROM entity_vote_redeem_logs
+			WHERE target_id = $1 AND target_type = $2 ORDER BY created_at DESC",
+		)
+		.bind(id)
+		.bind(self.entity.target_type())
+		.fetch_all(self.entity.pool())
+		.await?;
+
+		Ok(logs)
+	}
+
+	/// Returns the entities of this type with the most votes, using entity_approx_votes
+	/// 
+	/// Entities whose summary cannot be fetched are still returned with a summary of None
+	pub async fn leaderboard(&self, limit: i64, offset: i64) -> Result<Vec<LeaderboardEntry<E::SummaryObject>>, crate::Error> {
+		let rows: Vec<(String, i32)> = sqlx::query_as(
+			"SELECT target_id, approximate_votes FROM entity_approx_votes WHERE target_type = $1
+			ORDER BY approximate_votes DESC, target_id ASC LIMIT $2 OFFSET $3",
+		)
+		.bind(self.entity.target_type())
+		.bind(limit.clamp(0, MAX_LEADERBOARD_LIMIT))
+		.bind(offset.max(0))
+		.fetch_all(self.entity.pool())
+		.await?;
+
+		let summaries = futures::future::join_all(rows.iter().map(|(target_id, _)| self.get_summary(target_id))).await;
+
+		let mut entries = Vec::with_capacity(rows.len());
+		for (i, ((target_id, approximate_votes), summary)) in rows.into_iter().zip(summaries).enumerate() {
+			let summary = match summary {
+				Ok(summary) => Some(summary),
+				Err(e) => {
+					log::warn!("Failed to fetch summary for {} {target_id} on leaderboard: {e}", self.entity.target_type());
+					None
+				}
+			};
+
+			entries.push(LeaderboardEntry {
+				rank: offset.max(0) + i as i64 + 1,
+				target_id,
+				target_type: self.entity.target_type().to_string(),
+				approximate_votes,
+				summary,
+			});
+		}
+
+		Ok(entries)
+	}
+
+	/// Recomputes entity_approx_votes from entity_votes for a single entity, or every entity of this type if id is None
+	/// 
+	/// Entities are processed in batches of batch_size, each in its own short transaction, so the table is never
+	/// locked as a whole. Returns the entities whose counts changed
+	pub async fn reconcile_votes(&self, id: Option<&str>, batch_size: i64) -> Result<Vec<VoteCountChange>, crate::Error> {
+		if let Some(id) = id {
+			return self.reconcile_votes_batch(&[id.to_string()]).await;
+		}
+
+		let mut changes = Vec::new();
+		let mut last_id: Option<String> = None;
+		loop {
+			let ids: Vec<String> = sqlx::query_scalar(
+				"SELECT target_id FROM (
+					SELECT target_id FROM entity_votes WHERE target_type = $1
+					UNION
+					SELECT target_id FROM entity_approx_votes WHERE target_type = $1
+				) t WHERE ($2::TEXT IS NULL OR target_id > $2) ORDER BY target_id ASC LIMIT $3",
+			)
+			.bind(self.entity.target_type())
+			.bind(&last_id)
+			.bind(batch_size.max(1))
+			.fetch_all(self.entity.pool())
+			.await?;
+
+			let Some(last) = ids.last() else {
+				break;
+			};
+
+			last_id = Some(last.clone());
+			changes.extend(self.reconcile_votes_batch(&ids).await?);
+		}
+
+		Ok(changes)
+	}
+
+	/// Reconciles the approximate vote counts of the given entities in one transaction
+	async fn reconcile_votes_batch(&self, ids: &[String]) -> Result<Vec<VoteCountChange>, crate::Error> {
+		let mut tx = self.entity.pool().begin().await?;
+
+		// Lock the existing counts first so the exact counts below are read after any in-flight
+		// give_votes on these entities has committed
+		sqlx::query("SELECT 1 FROM entity_approx_votes WHERE target_type = $1 AND target_id = ANY($2) ORDER BY target_id FOR UPDATE")
+			.bind(self.entity.target_type())
+			.bind(ids)
+			.execute(&mut *tx)
+			.await?;
+
+		let changes: Vec<VoteCountChange> = sqlx::query_as(&format!(
+			"WITH exact AS (
+				SELECT t.target_id, COALESCE(SUM(CASE WHEN v.upvote THEN 1 ELSE -1 END), 0)::INTEGER AS votes
+				FROM UNNEST($2::TEXT[]) AS t(target_id)
+				LEFT JOIN entity_votes v ON v.target_id = t.target_id AND v.target_type = $1 AND {VALID_VOTES_SINCE_RESET}
+				GROUP BY t.target_id
+			), old AS (
+				SELECT target_id, approximate_votes FROM entity_approx_votes WHERE target_type = $1 AND target_id = ANY($2)
+			), upd AS (
+				INSERT INTO entity_approx_votes (target_id, target_type, approximate_votes)
+				SELECT e.target_id, $1, e.votes FROM exact e LEFT JOIN old o ON o.target_id = e.target_id
+				WHERE COALESCE(o.approximate_votes, 0) <> e.votes
+				ON CONFLICT (target_id, target_type) DO UPDATE SET approximate_votes = EXCLUDED.approximate_votes
+				RETURNING target_id, target_type, approximate_votes
+			)
+			SELECT upd.target_id, upd.target_type, COALESCE(o.approximate_votes, 0) AS old_votes, upd.approximate_votes AS new_votes
+			FROM upd LEFT JOIN old o ON o.target_id = upd.target_id"
+		))
+		.bind(self.entity.target_type())
+		.bind(ids)
+		.fetch_all(&mut *tx)
+		.await?;
+
+		tx.commit().await?;
+
+		for change in changes.iter() {
+			self.invalidate_cache(&change.target_id);
+		}
+
+		Ok(changes)
+	}
+
+	/// Resets the votes of every entity of this type for the given period
+	/// 
+	/// For each entity, its final vote count is archived to entity_vote_reset_history, all of its mutable votes
+	/// are voided and its approximate vote count is zeroed in one transaction. Each reset is recorded per period,
+	/// so calling this again for the same period resumes a crashed run and is a no-op once the run has completed
+	pub async fn reset_votes(&self, period: &str, batch_size: i64) -> Result<VoteReset, crate::Error> {
+		if period.is_empty() {
+			return Err("Vote reset period cannot be empty".into());
+		}
+
+		let reset: VoteReset = sqlx::query_as(
+			"INSERT INTO entity_vote_resets (period, target_type) VALUES ($1, $2)
+			ON CONFLICT (period, target_type) DO UPDATE SET period = EXCLUDED.period
+			RETURNING period, target_type, started_at, completed_at",
+		)
+		.bind(period)
+		.bind(self.entity.target_type())
+		.fetch_one(self.entity.pool())
+		.await?;
+
+		if reset.completed_at.is_some() {
+			return Ok(reset);
+		}
+
+		loop {
+			// Entities already archived for this period are skipped, so this always makes progress
+			let ids: Vec<String> = sqlx::query_scalar(
+				"SELECT target_id FROM (
+					SELECT target_id FROM entity_votes WHERE target_type = $1 AND void = false
+					UNION
+					SELECT target_id FROM entity_approx_votes WHERE target_type = $1 AND approximate_votes <> 0
+				) t WHERE NOT EXISTS (
+					SELECT 1 FROM entity_vote_reset_history h WHERE h.period = $2 AND h.target_type = $1 AND h.target_id = t.target_id
+				) ORDER BY target_id ASC LIMIT $3",
+			)
+			.bind(self.entity.target_type())
+			.bind(period)
+			.bind(batch_size.max(1))
+			.fetch_all(self.entity.pool())
+			.await?;
+
+			if ids.is_empty() {
+				break;
+			}
+
+			for id in ids {
+				self.reset_entity_votes(period, &id).await?;
+			}
+		}
+
+		let reset: VoteReset = sqlx::query_as(
+			"UPDATE entity_vote_resets SET completed_at = NOW() WHERE period = $1 AND target_type = $2
+			RETURNING period, target_type, started_at, completed_at",
+		)
+		.bind(period)
+		.bind(self.entity.target_type())
+		.fetch_one(self.entity.pool())
+		.await?;
+
+		Ok(reset)
+	}
+
+	/// Archives and resets the votes of a single entity for the given period
+	async fn reset_entity_votes(&self, period: &str, id: &str) -> Result<(), crate::Error> {
+		let mut tx = self.entity.pool().begin().await?;
+
+		// Lock the approximate count so concurrent votes are applied after the reset
+		sqlx::query("SELECT 1 FROM entity_approx_votes WHERE target_id = $1 AND target_type = $2 FOR UPDATE")
+			.bind(id)
+			.bind(self.entity.target_type())
+			.execute(&mut *tx)
+			.await?;
+
+		// The snapshot must be computed before the new history row exists
+		let votes: i64 = sqlx::query_scalar(&format!(
+			"SELECT COALESCE(SUM(CASE WHEN v.upvote THEN 1 ELSE -1 END), 0) FROM entity_votes v
+			WHERE v.target_id = $1 AND v.target_type = $2 AND {VALID_VOTES_SINCE_RESET}"
+		))
+		.bind(id)
+		.bind(self.entity.target_type())
+		.fetch_one(&mut *tx)
+		.await?;
+
+		let res = sqlx::query(
+			"INSERT INTO entity_vote_reset_history (period, target_id, target_type, votes) VALUES ($1, $2, $3, $4)
+			ON CONFLICT (period, target_id, target_type) DO NOTHING",
+		)
+		.bind(period)
+		.bind(id)
+		.bind(self.entity.target_type())
+		.bind(votes as i32)
+		.execute(&mut *tx)
+		.await?;
+
+		if res.rows_affected() == 0 {
+			// Already reset for this period
+			return Ok(());
+		}
+
+		sqlx::query(
+			"UPDATE entity_votes SET void = true, voided_at = NOW(), void_reason = $1
+			WHERE target_id = $2 AND target_type = $3 AND void = false AND immutable = false",
+		)
+		.bind(VOTE_RESET_REASON)
+		.bind(id)
+		.bind(self.entity.target_type())
 		.execute(&mut *tx)
 		.await?;
 
+		sqlx::query("UPDATE entity_approx_votes SET approximate_votes = 0 WHERE target_id = $1 AND target_type = $2")
+			.bind(id)
+			.bind(self.entity.target_type())
+			.execute(&mut *tx)
+			.await?;
+
 		tx.commit().await?;
 
+		self.invalidate_cache(id);
+
 		Ok(())
 	}
+
+	/// Returns the archived vote counts of an entity from previous vote resets, newest first
+	pub async fn fetch_vote_reset_history(&self, id: &str) -> Result<Vec<VoteResetSnapshot>, crate::Error> {
+		let history: Vec<VoteResetSnapshot> = sqlx::query_as(
+			"SELECT period, target_id, target_type, votes, created_at FROM entity_vote_reset_history
+			WHERE target_id = $1 AND target_type = $2 ORDER BY created_at DESC",
+		)
+		.bind(id)
+		.bind(self.entity.target_type())
+		.fetch_all(self.entity.pool())
+		.await?;
+
+		Ok(history)
+	}
+
+	/// Returns the amount of upvotes and downvotes an entity received in each bucket of the range [from, to)
+	/// 
+	/// Buckets are aligned to UTC and buckets without any votes are included with zero counts. Voided votes are excluded
+	pub async fn vote_time_series(
+		&self,
+		id: &str,
+		bucket: VoteBucket,
+		from: chrono::DateTime<chrono::Utc>,
+		to: chrono::DateTime<chrono::Utc>,
+	) -> Result<Vec<VoteTimeSeriesPoint>, crate::Error> {
+		if from >= to {
+			return Err(VoteTimeSeriesError::InvalidRange.into());
+		}
+
+		if bucket.bucket_count(from, to) > MAX_VOTE_TIME_SERIES_BUCKETS {
+			return Err(VoteTimeSeriesError::TooManyBuckets(MAX_VOTE_TIME_SERIES_BUCKETS).into());
+		}
+
+		let field = bucket.date_trunc_field();
+		let points: Vec<VoteTimeSeriesPoint> = sqlx::query_as(&format!(
+			"WITH buckets AS (
+				SELECT generate_series(
+					date_trunc('{field}', $3::TIMESTAMPTZ, 'UTC'),
+					$4::TIMESTAMPTZ - INTERVAL '1 microsecond',
+					INTERVAL '1 {field}'
+				) AS bucket
+			), counts AS (
+				SELECT date_trunc('{field}', created_at::TIMESTAMPTZ, 'UTC') AS bucket,
+				COUNT(*) FILTER (WHERE upvote) AS upvotes,
+				COUNT(*) FILTER (WHERE NOT upvote) AS downvotes
+				FROM entity_votes
+				WHERE target_id = $1 AND target_type = $2 AND void = false AND created_at >= $3 AND created_at < $4
+				GROUP BY 1
+			)
+			SELECT b.bucket, COALESCE(c.upvotes, 0) AS upvotes, COALESCE(c.downvotes, 0) AS downvotes
+			FROM buckets b LEFT JOIN counts c ON c.bucket = b.bucket
+			ORDER BY b.bucket ASC"
+		))
+		.bind(id)
+		.bind(self.entity.target_type())
+		.bind(from)
+		.bind(to)
+		.fetch_all(self.entity.pool())
+		.await?;
+
+		Ok(points)
+	}
+
+	/// Deletes an entity along with its votes, sessions, webhooks, known_entities row and CDN assets
+	/// 
+	/// All database rows are removed in one transaction, CDN assets are removed once it has committed
+	pub async fn delete(&self, id: &str) -> Result<(), crate::Error> {
+		let Some(id) = self.entity.resolve_id(id).await? else {
+			return Err(format!("{} not found", self.entity.name()).into());
+		};
+		let id = id.as_str();
+
+		let mut tx = self.entity.pool().begin().await?;
+
+		for table in ENTITY_OWNED_TABLES {
+			let exists: bool = sqlx::query_scalar("SELECT to_regclass($1) IS NOT NULL")
+				.bind(table)
+				.fetch_one(&mut *tx)
+				.await?;
+
+			if !exists {
+				continue;
+			}
+
+			sqlx::query(&format!("DELETE FROM {table} WHERE target_id = $1 AND target_type = $2"))
+				.bind(id)
+				.bind(self.entity.target_type())
+				.execute(&mut *tx)
+				.await?;
+		}
+
+		self.entity.delete(&mut *tx, id).await?;
+
+		tx.commit().await?;
+
+		self.invalidate_cache(id);
+
+		for asset_type in [AssetType::Avatar, AssetType::Banner] {
+			if let Err(e) = delete_asset(&self.entity, id, asset_type) {
+				log::error!("Failed to delete {} of {} {id}: {e}", asset_type.as_str(), self.entity.target_type());
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Creates a new entity, registering it in known_entities in the same transaction
+	pub async fn create(&self, obj: E::CreateObject) -> Result<String, crate::Error> {
+		let mut tx = self.entity.pool().begin().await?;
+
+		let id = self.entity.create(&mut *tx, obj).await?;
+
+		sqlx::query("INSERT INTO known_entities (target_id, target_type) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+			.bind(&id)
+			.bind(self.entity.target_type())
+			.execute(&mut *tx)
+			.await?;
+
+		tx.commit().await?;
+
+		Ok(id)
+	}
 }
\ No newline at end of file
@@ -1,10 +1,18 @@
 pub mod entities;
 pub mod manager;
 pub mod lua;
+pub mod list;
+pub mod webhooks;
+pub mod flags;
+pub mod cache;
+pub mod events;
+pub mod multipliers;
 
 use bitflags::bitflags;
 use serde::{Deserialize, Serialize};
 
+use list::{EntityList, EntityListQuery};
+
 // an integer and each bit is a flag. Just | and & operators
 bitflags! {
     #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -57,6 +65,10 @@ pub trait Entity: 'static + Send + Sync + Clone {
     type SummaryObject: Serialize + for<'de> Deserialize<'de> + Send + Sync;
     /// The create object type for the entity
     type CreateObject: Serialize + for<'de> Deserialize<'de> + Send + Sync;
+    /// The update object type for the entity, all fields should be optional with None meaning unchanged
+    type UpdateObject: Serialize + for<'de> Deserialize<'de> + Send + Sync;
+    /// The type specific filters used when listing entities
+    type ListFilter: Serialize + for<'de> Deserialize<'de> + Default + Send + Sync;
 
     /// Returns the underlying pool used by this entity
     /// 
@@ -78,6 +90,8 @@ pub trait Entity: 'static + Send + Sync + Clone {
     fn cdn_folder(&self) -> &'static str;
 
     /// Returns the flags for the given ID.
+    ///
+    /// These do not include the overrides stored in entity_flags, use EntityManager::flags to get the resolved flags
     async fn flags(&self, _id: &str) -> Result<EntityFlags, crate::Error> {
         Ok(EntityFlags::NONE)
     }
@@ -87,14 +101,11 @@ pub trait Entity: 'static + Send + Sync + Clone {
 
     /// Returns core vote info about the entity (such as the amount of cooldown time the entity has)
     ///
-    /// If user id is specified, then in the future special perks for the user will be returned as well
+    /// If user id is specified, the perks of the user are taken into account as well
     ///
-    /// If vote time is negative, then it is not possible to revote
-    async fn get_vote_info(&self, _id: &str, _user_id: Option<&str>) -> Result<EntityVoteInfo, crate::Error> {
-        Ok(EntityVoteInfo {
-            per_user: 1, // 1 vote per user
-            vote_time: 12 // per day
-        })
+    /// By default, this evaluates the vote rules of the target type for a non-premium entity (see multipliers.rs)
+    async fn get_vote_info(&self, id: &str, user_id: Option<&str>) -> Result<EntityVoteInfo, crate::Error> {
+        multipliers::compute_vote_info(self.pool(), self.target_type(), id, false, user_id).await
     }
 
     /// Fetches the full object for the entity
@@ -106,10 +117,37 @@ pub trait Entity: 'static + Send + Sync + Clone {
     /// Fetches the summary (short form) object for the entity
     async fn get_summary(&self, _id: &str) -> Result<Self::SummaryObject, crate::Error>;
 
-    /// Creates a new entity from the given create object returning the ID of the created entity
-    async fn create(&self, _obj: Self::CreateObject) -> Result<String, crate::Error> {
+    /// Creates a new entity from the given create object using the given connection, returning the ID of the created entity
+    ///
+    /// EntityManager::create should be used instead of calling this directly so the entity is registered in known_entities
+    async fn create(&self, _conn: &mut sqlx::PgConnection, _obj: Self::CreateObject) -> Result<String, crate::Error> {
         Err("Entity creation not implemented for this entity type".into())
     }
+
+    /// Resolves the given ID (which may be in a non-canonical form) to the ID stored in the database
+    ///
+    /// Returns None if no entity can have the given ID
+    async fn resolve_id(&self, id: &str) -> Result<Option<String>, crate::Error> {
+        Ok(Some(id.to_string()))
+    }
+
+    /// Applies a partial update to the entity with the given ID
+    async fn update(&self, _id: &str, _obj: Self::UpdateObject) -> Result<(), crate::Error> {
+        Err("Entity updates not implemented for this entity type".into())
+    }
+
+    /// Deletes the entity row(s) of the given ID using the given connection
+    ///
+    /// Shared data (votes, sessions, known_entities, CDN assets etc.) is cleaned up by EntityManager::delete
+    /// which should be used instead of calling this directly
+    async fn delete(&self, _conn: &mut sqlx::PgConnection, _id: &str) -> Result<(), crate::Error> {
+        Err("Entity deletion not implemented for this entity type".into())
+    }
+
+    /// Lists (and optionally searches) entities using cursor pagination
+    async fn list(&self, _query: EntityListQuery<Self::ListFilter>) -> Result<EntityList<Self::SummaryObject>, crate::Error> {
+        Err("Entity listing not implemented for this entity type".into())
+    }
 }
 
 /// Macro to create a enum of entity types
@@ -117,11 +155,11 @@ pub trait Entity: 'static + Send + Sync + Clone {
 /// # Example
 /// ```ignore
 /// entity_enum! {
-///     Bot = (BotEntity, "bot" | "bots", FullBotObject, PublicBotObject, SummaryBotObject),
+///     Bot = (BotEntity, "bot" | "bots", FullBotObject, PublicBotObject, SummaryBotObject, CreateBotObject, UpdateBotObject),
 ///  }
 #[macro_export]
 macro_rules! entity_enum {
-    ($( $name:ident = ( $entity_type:ty, $matcher:pat, $full_type:ty, $public_type:ty, $summary_type:ty, $create_type:ty ) ),* $(,)?) => {
+    ($( $name:ident = ( $entity_type:ty, $matcher:pat, $full_type:ty, $public_type:ty, $summary_type:ty, $create_type:ty, $update_type:ty ) ),* $(,)?) => {
         #[allow(dead_code)]
         pub type AnyEntityManager = crate::entity::manager::EntityManager<EntityType>;
 
@@ -150,7 +188,7 @@ macro_rules! entity_enum {
         pub enum EntityEnumPublicObject {
             $( $name( $public_type ), )*
         } 
-        #[derive(Debug, Serialize, Deserialize, utoipa::ToSchema)]
+        #[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
         #[serde(tag = "type")]
         pub enum EntityEnumSummaryObject {
             $( $name( $summary_type ), )*
@@ -160,6 +198,11 @@ macro_rules! entity_enum {
         pub enum EntityEnumCreateObject {
             $( $name( $create_type ), )*
         }
+        #[derive(Debug, Serialize, Deserialize, utoipa::ToSchema)]
+        #[serde(tag = "type")]
+        pub enum EntityEnumUpdateObject {
+            $( $name( $update_type ), )*
+        }
 
         #[allow(unused_variables)]
         impl Entity for EntityType {
@@ -167,6 +210,9 @@ macro_rules! entity_enum {
             type PublicObject = EntityEnumPublicObject;
             type SummaryObject = EntityEnumSummaryObject;
             type CreateObject = EntityEnumCreateObject;
+            type UpdateObject = EntityEnumUpdateObject;
+            /// Filters are deserialized into the filter type of the underlying entity, null means no filters
+            type ListFilter = serde_json::Value;
 
             fn pool(&self) -> &sqlx::PgPool {
                 match self {
@@ -243,10 +289,50 @@ macro_rules! entity_enum {
                 }
             }
 
-            async fn create(&self, obj: Self::CreateObject) -> Result<String, crate::Error> {
+            async fn create(&self, conn: &mut sqlx::PgConnection, obj: Self::CreateObject) -> Result<String, crate::Error> {
                 match (self, obj) {
                     $( (Self::$name(e), EntityEnumCreateObject::$name(create_obj)) => {
-                        e.create(create_obj).await
+                        e.create(conn, create_obj).await
+                    }, )*
+                    _ => Err(format!("Create object does not match entity type {}", self.name()).into()),
+                }
+            }
+
+            async fn resolve_id(&self, id: &str) -> Result<Option<String>, crate::Error> {
+                match self {
+                    $( Self::$name(e) => e.resolve_id(id).await, )*
+                }
+            }
+
+            async fn update(&self, id: &str, obj: Self::UpdateObject) -> Result<(), crate::Error> {
+                match (self, obj) {
+                    $( (Self::$name(e), EntityEnumUpdateObject::$name(update_obj)) => {
+                        e.update(id, update_obj).await
+                    }, )*
+                    _ => Err(format!("Update object does not match entity type {}", self.name()).into()),
+                }
+            }
+
+            async fn delete(&self, conn: &mut sqlx::PgConnection, id: &str) -> Result<(), crate::Error> {
+                match self {
+                    $( Self::$name(e) => e.delete(conn, id).await, )*
+                }
+            }
+
+            async fn list(&self, query: list::EntityListQuery<Self::ListFilter>) -> Result<list::EntityList<Self::SummaryObject>, crate::Error> {
+                match self {
+                    $( Self::$name(e) => {
+                        let filter = if query.filter.is_null() {
+                            Default::default()
+                        } else {
+                            serde_json::from_value(query.filter.clone()).map_err(|e| format!("Invalid filter: {e}"))?
+                        };
+
+                        let page = e.list(query.with_filter(filter)).await?;
+                        Ok(list::EntityList {
+                            items: page.items.into_iter().map(EntityEnumSummaryObject::$name).collect(),
+                            next_cursor: page.next_cursor,
+                        })
                     }, )*
                 }
             }
@@ -255,5 +341,9 @@ macro_rules! entity_enum {
 }
 
 entity_enum! {
-    Dummy = (entities::Dummy, "dummy" | "dodo", entities::DummyObj, entities::DummyObj, entities::DummyObj, entities::DummyObj),
+    Dummy = (entities::Dummy, "dummy" | "dodo", entities::DummyObj, entities::DummyObj, entities::DummyObj, entities::DummyObj, entities::DummyObj),
+    Bot = (entities::Bot, "bot" | "bots", entities::BotFull, entities::BotPublic, entities::BotSummary, entities::BotCreate, entities::BotUpdate),
+    User = (entities::User, "user" | "users", entities::UserFull, entities::UserPublic, entities::UserSummary, entities::UserCreate, entities::UserUpdate),
+    Server = (entities::Server, "server" | "servers", entities::ServerFull, entities::ServerPublic, entities::ServerSummary, entities::ServerCreate, entities::ServerUpdate),
+    Team = (entities::Team, "team" | "teams", entities::TeamFull, entities::TeamPublic, entities::TeamSummary, entities::TeamCreate, entities::TeamUpdate),
 }
\ No newline at end of file
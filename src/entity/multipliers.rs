@@ -0,0 +1,361 @@
+use chrono::{DateTime, Datelike, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+
+use crate::config::CONFIG;
+
+use super::EntityVoteInfo;
+
+/// The kittycat permission needed to replace or reset the vote rules of a target type
+pub const SET_VOTE_RULES_PERM: &str = "vote_rules.set";
+
+#[derive(Debug)]
+/// Errors returned when changing the vote rules of a target type
+///
+/// These are boxed into crate::Error and can be recovered with downcast_ref
+pub enum VoteRulesError {
+    /// The actor lacks the vote_rules.set staff permission
+    MissingPerm,
+    /// The target type is not a known entity type
+    UnknownTargetType(String),
+    /// The rules would never let anyone vote
+    Invalid(&'static str),
+}
+
+impl std::fmt::Display for VoteRulesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingPerm => write!(f, "You need the {SET_VOTE_RULES_PERM} permission to change vote rules"),
+            Self::UnknownTargetType(target_type) => write!(f, "Unknown target type: {target_type}"),
+            Self::Invalid(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+impl std::error::Error for VoteRulesError {}
+
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+/// A rule that changes the votes given by a single vote when it matches
+///
+/// `multiplier` multiplies the amount of votes and `vote_time` (in hours) caps the cooldown
+pub enum MultiplierRule {
+    /// Matches premium entities
+    Premium {
+        #[serde(default = "default_multiplier")]
+        multiplier: u8,
+        #[serde(default)]
+        vote_time: Option<u16>,
+    },
+    /// Matches on saturdays and sundays in the timezone of the entity, falling back to the given timezone (UTC if unset)
+    Weekend {
+        #[serde(default = "default_multiplier")]
+        multiplier: u8,
+        #[serde(default)]
+        vote_time: Option<u16>,
+        #[serde(default)]
+        #[schema(value_type = Option<String>)]
+        timezone: Option<chrono_tz::Tz>,
+    },
+    /// Matches users with the given perk
+    UserPerk {
+        perk: String,
+        #[serde(default = "default_multiplier")]
+        multiplier: u8,
+        #[serde(default)]
+        vote_time: Option<u16>,
+    },
+}
+
+fn default_multiplier() -> u8 {
+    1
+}
+
+impl MultiplierRule {
+    fn matches(&self, ctx: &VoteContext) -> bool {
+        match self {
+            Self::Premium { .. } => ctx.premium,
+            Self::Weekend { timezone, .. } => {
+                let weekday = match ctx.timezone.or(*timezone) {
+                    Some(tz) => ctx.now.with_timezone(&tz).weekday(),
+                    None => ctx.now.weekday(),
+                };
+                matches!(weekday, Weekday::Sat | Weekday::Sun)
+            }
+            Self::UserPerk { perk, .. } => ctx.user_perks.iter().any(|p| p == perk),
+        }
+    }
+
+    fn effect(&self) -> (u8, Option<u16>) {
+        match self {
+            Self::Premium { multiplier, vote_time }
+            | Self::Weekend { multiplier, vote_time, .. }
+            | Self::UserPerk { multiplier, vote_time, .. } => (*multiplier, *vote_time),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+/// The rules used to compute the vote info of an entity type
+pub struct VoteRules {
+    /// The amount of votes a single vote gives before any rules are applied
+    pub base_per_user: u8,
+    /// The cooldown between votes in hours before any rules are applied
+    pub base_vote_time: u16,
+    /// Every matching rule is applied, multipliers stack and the lowest vote time wins
+    pub rules: Vec<MultiplierRule>,
+}
+
+impl Default for VoteRules {
+    /// Premium entities get double votes
+    fn default() -> Self {
+        Self {
+            base_per_user: 1,
+            base_vote_time: 12,
+            rules: vec![MultiplierRule::Premium {
+                multiplier: 2,
+                vote_time: None,
+            }],
+        }
+    }
+}
+
+/// What the rules are evaluated against
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoteContext {
+    /// Whether or not the entity is premium
+    pub premium: bool,
+    /// The perks of the voting user, empty if no user was given
+    #[serde(default)]
+    pub user_perks: Vec<String>,
+    /// The timezone of the entity, takes priority over the timezone of weekend rules
+    #[serde(default)]
+    pub timezone: Option<chrono_tz::Tz>,
+    pub now: DateTime<Utc>,
+}
+
+impl VoteRules {
+    /// Returns an error if the rules would never let anyone vote
+    pub fn validate(&self) -> Result<(), VoteRulesError> {
+        if self.base_per_user == 0 {
+            return Err(VoteRulesError::Invalid("base_per_user must be at least 1"));
+        }
+
+        for rule in self.rules.iter() {
+            if rule.effect().0 == 0 {
+                return Err(VoteRulesError::Invalid("Rule multipliers must be at least 1"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies all matching rules, the amount of votes is capped at 255
+    pub fn evaluate(&self, ctx: &VoteContext) -> EntityVoteInfo {
+        let mut per_user = self.base_per_user as u32;
+        let mut vote_time = self.base_vote_time;
+
+        for rule in self.rules.iter().filter(|rule| rule.matches(ctx)) {
+            let (multiplier, rule_vote_time) = rule.effect();
+            per_user = per_user.saturating_mul(multiplier as u32);
+            if let Some(rule_vote_time) = rule_vote_time {
+                vote_time = vote_time.min(rule_vote_time);
+            }
+        }
+
+        EntityVoteInfo {
+            per_user: per_user.min(u8::MAX as u32) as u8,
+            vote_time,
+        }
+    }
+}
+
+/// Returns the vote rules of a target type
+///
+/// Rules stored in entity_vote_rules take priority over the config, falling back to VoteRules::default.
+/// These are read from the database every time so changes apply to every process immediately
+pub async fn vote_rules_for(pool: &sqlx::PgPool, target_type: &str) -> Result<VoteRules, crate::Error> {
+    let rules: Option<sqlx::types::Json<VoteRules>> = sqlx::query_scalar("SELECT rules FROM entity_vote_rules WHERE target_type = $1")
+        .bind(target_type)
+        .fetch_optional(pool)
+        .await?;
+
+    match rules {
+        Some(rules) => Ok(rules.0),
+        None => Ok(CONFIG.vote_rules.get(target_type).cloned().unwrap_or_default()),
+    }
+}
+
+/// Stores new vote rules for a target type, overriding the ones in the config
+///
+/// This does not check permissions, use SharedLayer::set_vote_rules for staff-gated changes
+pub async fn set_vote_rules(pool: &sqlx::PgPool, target_type: &str, rules: &VoteRules, actor_id: &str) -> Result<(), crate::Error> {
+    rules.validate()?;
+
+    sqlx::query(
+        "INSERT INTO entity_vote_rules (target_type, rules, updated_by) VALUES ($1, $2, $3)
+        ON CONFLICT (target_type) DO UPDATE SET rules = EXCLUDED.rules, updated_by = EXCLUDED.updated_by, updated_at = NOW()",
+    )
+    .bind(target_type)
+    .bind(sqlx::types::Json(rules))
+    .bind(actor_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Removes the stored vote rules of a target type, reverting to the ones in the config
+///
+/// This does not check permissions, use SharedLayer::reset_vote_rules for staff-gated changes
+pub async fn reset_vote_rules(pool: &sqlx::PgPool, target_type: &str) -> Result<(), crate::Error> {
+    sqlx::query("DELETE FROM entity_vote_rules WHERE target_type = $1")
+        .bind(target_type)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Returns the perks of a user used by UserPerk rules
+///
+/// Returns no perks if the user does not exist
+pub async fn fetch_user_perks(pool: &sqlx::PgPool, user_id: &str) -> Result<Vec<String>, crate::Error> {
+    let row: Option<(bool, bool, bool)> = sqlx::query_as("SELECT certified, developer, bug_hunters FROM users WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+    let Some((certified, developer, bug_hunters)) = row else {
+        return Ok(Vec::new());
+    };
+
+    let perks = [("certified", certified), ("developer", developer), ("bug_hunter", bug_hunters)]
+        .into_iter()
+        .filter(|(_, has)| *has)
+        .map(|(perk, _)| perk.to_string())
+        .collect();
+
+    Ok(perks)
+}
+
+/// Returns the timezone of an entity stored in known_entities
+///
+/// Returns None if the entity has no (valid) timezone set
+pub async fn fetch_entity_timezone(pool: &sqlx::PgPool, target_type: &str, id: &str) -> Result<Option<chrono_tz::Tz>, crate::Error> {
+    let timezone: Option<Option<String>> = sqlx::query_scalar("SELECT timezone FROM known_entities WHERE target_id = $1 AND target_type = $2")
+        .bind(id)
+        .bind(target_type)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(timezone.flatten().and_then(|tz| tz.parse().ok()))
+}
+
+/// Computes the vote info of an entity using the vote rules of its target type
+pub async fn compute_vote_info(
+    pool: &sqlx::PgPool,
+    target_type: &str,
+    id: &str,
+    premium: bool,
+    user_id: Option<&str>,
+) -> Result<EntityVoteInfo, crate::Error> {
+    let user_perks = match user_id {
+        Some(user_id) => fetch_user_perks(pool, user_id).await?,
+        None => Vec::new(),
+    };
+
+    let ctx = VoteContext {
+        premium,
+        user_perks,
+        timezone: fetch_entity_timezone(pool, target_type, id).await?,
+        now: Utc::now(),
+    };
+
+    Ok(vote_rules_for(pool, target_type).await?.evaluate(&ctx))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn ctx(premium: bool, perks: &[&str], now: DateTime<Utc>) -> VoteContext {
+        VoteContext {
+            premium,
+            user_perks: perks.iter().map(|p| p.to_string()).collect(),
+            timezone: None,
+            now,
+        }
+    }
+
+    #[test]
+    fn test_vote_rules() {
+        // A friday at 23:00 UTC, which is already saturday in Tokyo
+        let friday = Utc.with_ymd_and_hms(2024, 6, 7, 23, 0, 0).unwrap();
+
+        let rules = VoteRules {
+            base_per_user: 1,
+            base_vote_time: 12,
+            rules: vec![
+                MultiplierRule::Premium { multiplier: 2, vote_time: None },
+                MultiplierRule::Weekend { multiplier: 2, vote_time: None, timezone: Some(chrono_tz::Asia::Tokyo) },
+                MultiplierRule::UserPerk { perk: "certified".to_string(), multiplier: 1, vote_time: Some(6) },
+            ],
+        };
+
+        let vi = rules.evaluate(&ctx(false, &[], friday));
+        assert_eq!((vi.per_user, vi.vote_time), (2, 12));
+
+        let vi = rules.evaluate(&ctx(true, &["certified"], friday));
+        assert_eq!((vi.per_user, vi.vote_time), (4, 6));
+
+        // Not the weekend in UTC
+        let rules_utc = VoteRules {
+            rules: vec![MultiplierRule::Weekend { multiplier: 2, vote_time: None, timezone: None }],
+            ..VoteRules::default()
+        };
+        assert_eq!(rules_utc.evaluate(&ctx(false, &[], friday)).per_user, 1);
+
+        // The timezone of the entity takes priority over the one of the rule
+        let entity_ctx = VoteContext {
+            timezone: Some(chrono_tz::America::New_York),
+            ..ctx(false, &[], friday)
+        };
+        assert_eq!(rules.evaluate(&entity_ctx).per_user, 1);
+
+        let entity_ctx = VoteContext {
+            timezone: Some(chrono_tz::Asia::Tokyo),
+            ..ctx(false, &[], friday)
+        };
+        assert_eq!(rules_utc.evaluate(&entity_ctx).per_user, 2);
+    }
+
+    #[test]
+    fn test_vote_rules_cap() {
+        let rules = VoteRules {
+            base_per_user: 100,
+            base_vote_time: 12,
+            rules: vec![MultiplierRule::Premium { multiplier: 100, vote_time: None }],
+        };
+        assert_eq!(rules.evaluate(&ctx(true, &[], Utc::now())).per_user, u8::MAX);
+    }
+
+    #[test]
+    fn test_vote_rules_deserialize() {
+        let rules: VoteRules = serde_json::from_value(serde_json::json!({
+            "base_per_user": 1,
+            "base_vote_time": 12,
+            "rules": [
+                {"type": "premium", "multiplier": 2},
+                {"type": "weekend", "multiplier": 2, "timezone": "America/New_York"},
+                {"type": "user_perk", "perk": "developer", "vote_time": 6},
+            ],
+        }))
+        .unwrap();
+
+        assert!(rules.validate().is_ok());
+        assert!(matches!(rules.rules[1], MultiplierRule::Weekend { timezone: Some(chrono_tz::America::New_York), .. }));
+        assert!(matches!(rules.rules[2], MultiplierRule::UserPerk { multiplier: 1, .. }));
+    }
+}
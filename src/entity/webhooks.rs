@@ -0,0 +1,499 @@
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::config::CONFIG;
+
+/// Header containing the hex encoded HMAC-SHA256 signature of the request
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+/// Header containing the unix timestamp (in seconds) the request was signed at
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+
+/// Maximum length of a response body stored in webhook_logs
+const MAX_LOGGED_RESPONSE_LEN: usize = 4096;
+
+/// How long a claimed delivery is hidden from other pollers while it is being attempted
+const DELIVERY_LEASE: Duration = Duration::from_secs(60);
+/// How often pending deliveries are polled for by default
+pub const DEFAULT_WEBHOOK_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// The maximum amount of pending deliveries claimed per poll
+pub const WEBHOOK_BATCH_SIZE: i64 = 50;
+
+/// Shared HTTP client for webhook deliveries, all requests go through the configured proxy
+static WEBHOOK_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    let mut builder = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .redirect(reqwest::redirect::Policy::none());
+
+    if !CONFIG.proxy_url.is_empty() {
+        builder = builder.proxy(reqwest::Proxy::all(&CONFIG.proxy_url).expect("Invalid proxy URL"));
+    }
+
+    builder.build().expect("Failed to create webhook client")
+});
+
+/// A webhook attached to an entity
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Webhook {
+    pub id: uuid::Uuid,
+    pub target_id: String,
+    pub target_type: String,
+    pub name: String,
+    pub url: String,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    /// If true, the secret is sent as-is in the Authorization header as well
+    pub simple_auth: bool,
+    /// Events the webhook is sent, an empty list means all events
+    pub event_whitelist: Vec<String>,
+    /// Set once a webhook has failed too many deliveries in a row, broken webhooks are never sent
+    pub broken: bool,
+    /// The amount of consecutive failed deliveries
+    pub failed_requests: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+const WEBHOOK_COLS: &str = "id, target_id, target_type, name, url, secret, simple_auth, event_whitelist, broken, failed_requests, created_at::TIMESTAMPTZ AS created_at";
+
+/// An event that can be sent to a webhook
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum WebhookEvent {
+    /// A user voted on the entity
+    NewVote {
+        user_id: String,
+        upvote: bool,
+        /// The amount of votes given to the entity
+        votes: u8,
+    },
+}
+
+impl WebhookEvent {
+    /// Returns the name of the event, used for event whitelists
+    pub fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::NewVote { .. } => "NewVote",
+        }
+    }
+
+    /// Returns the user who triggered the event
+    pub fn user_id(&self) -> &str {
+        match self {
+            WebhookEvent::NewVote { user_id, .. } => user_id,
+        }
+    }
+}
+
+/// The body of a webhook request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub target_id: String,
+    pub target_type: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: WebhookEvent,
+}
+
+/// Controls how failed deliveries are retried
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// The maximum amount of attempts per delivery
+    pub max_tries: i32,
+    /// The delay before the first retry, doubled on every retry after
+    pub base_delay: Duration,
+    /// The amount of consecutive failed deliveries after which a webhook is marked as broken
+    pub max_failed_requests: i32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_tries: 5,
+            base_delay: Duration::from_secs(2),
+            max_failed_requests: 10,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the delay before the given (1-indexed) retry
+    pub fn backoff(&self, retry: i32) -> Duration {
+        self.base_delay * 2u32.saturating_pow((retry - 1).max(0) as u32)
+    }
+}
+
+/// The result of a single delivery attempt
+#[derive(Debug)]
+pub struct DeliveryAttempt {
+    pub status_code: u16,
+    pub response: String,
+    pub request_headers: serde_json::Value,
+    pub response_headers: serde_json::Value,
+}
+
+impl DeliveryAttempt {
+    /// Whether the webhook accepted the request
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status_code)
+    }
+
+    /// Whether the delivery should be retried. Client errors other than rate limits are never retried
+    pub fn is_retryable(&self) -> bool {
+        self.status_code == 429 || self.status_code >= 500
+    }
+}
+
+/// Returns the hex encoded HMAC-SHA256 signature of a webhook request
+///
+/// The signature is computed over `{timestamp}.{body}` so a captured request cannot be replayed with a different timestamp
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Makes a single signed POST request to a webhook
+pub async fn send_once(
+    client: &reqwest::Client,
+    url: &str,
+    secret: &str,
+    simple_auth: bool,
+    body: &str,
+) -> Result<DeliveryAttempt, reqwest::Error> {
+    let timestamp = Utc::now().timestamp();
+
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert(reqwest::header::CONTENT_TYPE, reqwest::header::HeaderValue::from_static("application/json"));
+    headers.insert(TIMESTAMP_HEADER, timestamp.into());
+    headers.insert(
+        SIGNATURE_HEADER,
+        sign(secret, timestamp, body).parse().expect("hex is a valid header value"),
+    );
+
+    let mut logged_headers = headers_to_json(&headers);
+
+    if simple_auth {
+        if let Ok(value) = secret.parse() {
+            headers.insert(reqwest::header::AUTHORIZATION, value);
+        }
+        // Never store the secret in the logs
+        logged_headers[reqwest::header::AUTHORIZATION.as_str()] = "[redacted]".into();
+    }
+
+    let resp = client.post(url).headers(headers).body(body.to_string()).send().await?;
+
+    let status_code = resp.status().as_u16();
+    let response_headers = headers_to_json(resp.headers());
+    let mut response = resp.text().await.unwrap_or_default();
+    if response.len() > MAX_LOGGED_RESPONSE_LEN {
+        let mut end = MAX_LOGGED_RESPONSE_LEN;
+        while !response.is_char_boundary(end) {
+            end -= 1;
+        }
+        response.truncate(end);
+    }
+
+    Ok(DeliveryAttempt {
+        status_code,
+        response,
+        request_headers: logged_headers,
+        response_headers,
+    })
+}
+
+fn headers_to_json(headers: &reqwest::header::HeaderMap) -> serde_json::Value {
+    let map = headers
+        .iter()
+        .map(|(k, v)| (k.to_string(), serde_json::Value::String(v.to_str().unwrap_or_default().to_string())))
+        .collect::<serde_json::Map<_, _>>();
+    serde_json::Value::Object(map)
+}
+
+/// Sends events to the webhooks of entities, logging every delivery to webhook_logs
+#[derive(Clone)]
+pub struct WebhookDispatcher {
+    pool: sqlx::PgPool,
+    client: reqwest::Client,
+    policy: RetryPolicy,
+}
+
+impl WebhookDispatcher {
+    /// Creates a new dispatcher using the shared webhook client and the default retry policy
+    pub fn new(pool: sqlx::PgPool) -> Self {
+        Self::with_client(pool, WEBHOOK_CLIENT.clone(), RetryPolicy::default())
+    }
+
+    /// Creates a new dispatcher with a custom client and retry policy
+    pub fn with_client(pool: sqlx::PgPool, client: reqwest::Client, policy: RetryPolicy) -> Self {
+        Self { pool, client, policy }
+    }
+
+    /// Returns all webhooks of an entity that should receive the given event
+    pub async fn webhooks_for(&self, target_id: &str, target_type: &str, event: &WebhookEvent) -> Result<Vec<Webhook>, crate::Error> {
+        let webhooks: Vec<Webhook> = sqlx::query_as(&format!(
+            "SELECT {WEBHOOK_COLS} FROM webhooks WHERE target_id = $1 AND target_type = $2 AND broken = false
+            AND (cardinality(event_whitelist) = 0 OR $3 = ANY(event_whitelist))"
+        ))
+        .bind(target_id)
+        .bind(target_type)
+        .bind(event.name())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(webhooks)
+    }
+
+    /// Queues an event for delivery to all matching webhooks of an entity
+    ///
+    /// The first attempt happens in the background right away, retries are persisted in webhook_logs and picked
+    /// up by the retry poller (see spawn_retry_poller). The IDs of the created webhook_logs rows are returned
+    pub async fn dispatch(&self, target_id: &str, target_type: &str, event: WebhookEvent) -> Result<Vec<uuid::Uuid>, crate::Error> {
+        let webhooks = self.webhooks_for(target_id, target_type, &event).await?;
+
+        if webhooks.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let payload = WebhookPayload {
+            target_id: target_id.to_string(),
+            target_type: target_type.to_string(),
+            created_at: Utc::now(),
+            event,
+        };
+        let data = serde_json::to_value(&payload)?;
+
+        let mut log_ids = Vec::with_capacity(webhooks.len());
+        for webhook in webhooks {
+            // Leased so the poller only picks the delivery up if this process dies before attempting it
+            let log_id: uuid::Uuid = sqlx::query_scalar(
+                "INSERT INTO webhook_logs (target_id, target_type, user_id, url, data, bad_intent, webhook_id, next_attempt_at)
+                VALUES ($1, $2, $3, $4, $5, false, $6, NOW() + make_interval(secs => $7)) RETURNING id",
+            )
+            .bind(target_id)
+            .bind(target_type)
+            .bind(payload.event.user_id())
+            .bind(&webhook.url)
+            .bind(&data)
+            .bind(webhook.id)
+            .bind(DELIVERY_LEASE.as_secs_f64())
+            .fetch_one(&self.pool)
+            .await?;
+
+            log_ids.push(log_id);
+
+            let dispatcher = self.clone();
+            let body = data.to_string();
+            tokio::spawn(async move {
+                if let Err(e) = dispatcher.attempt(log_id, &webhook, &body, 0).await {
+                    log::error!("Failed to deliver webhook {}: {e}", webhook.id);
+                }
+            });
+        }
+
+        Ok(log_ids)
+    }
+
+    /// Makes one delivery attempt for a logged webhook request, then either resolves the log or schedules
+    /// the next attempt with exponential backoff
+    ///
+    /// `tries` is the amount of attempts already made. Returns true if the delivery succeeded
+    pub async fn attempt(&self, log_id: uuid::Uuid, webhook: &Webhook, body: &str, tries: i32) -> Result<bool, crate::Error> {
+        let tries = tries + 1;
+
+        let (success, retryable) = match send_once(&self.client, &webhook.url, &webhook.secret, webhook.simple_auth, body).await {
+            Ok(attempt) => {
+                sqlx::query(
+                    "UPDATE webhook_logs SET tries = $1, last_try = NOW(), status_code = $2, response = $3,
+                    request_headers = $4, response_headers = $5 WHERE id = $6",
+                )
+                .bind(tries)
+                .bind(attempt.status_code as i32)
+                .bind(&attempt.response)
+                .bind(&attempt.request_headers)
+                .bind(&attempt.response_headers)
+                .bind(log_id)
+                .execute(&self.pool)
+                .await?;
+
+                (attempt.is_success(), attempt.is_retryable())
+            }
+            Err(e) => {
+                sqlx::query("UPDATE webhook_logs SET tries = $1, last_try = NOW(), status_code = 0, response = $2 WHERE id = $3")
+                    .bind(tries)
+                    .bind(e.to_string())
+                    .bind(log_id)
+                    .execute(&self.pool)
+                    .await?;
+
+                (false, true)
+            }
+        };
+
+        if success {
+            let mut tx = self.pool.begin().await?;
+            sqlx::query("UPDATE webhook_logs SET state = 'SUCCESS' WHERE id = $1")
+                .bind(log_id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("UPDATE webhooks SET failed_requests = 0 WHERE id = $1")
+                .bind(webhook.id)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+
+            return Ok(true);
+        }
+
+        if retryable && tries < self.policy.max_tries {
+            sqlx::query("UPDATE webhook_logs SET next_attempt_at = NOW() + make_interval(secs => $1) WHERE id = $2")
+                .bind(self.policy.backoff(tries).as_secs_f64())
+                .bind(log_id)
+                .execute(&self.pool)
+                .await?;
+
+            return Ok(false);
+        }
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE webhook_logs SET state = 'FAILED' WHERE id = $1")
+            .bind(log_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE webhooks SET failed_requests = failed_requests + 1, broken = (failed_requests + 1 >= $1) WHERE id = $2")
+            .bind(self.policy.max_failed_requests)
+            .bind(webhook.id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(false)
+    }
+
+    /// Claims up to limit pending deliveries whose next attempt is due and attempts each of them
+    ///
+    /// Claimed deliveries are leased so concurrent pollers (and other instances) skip them. Deliveries whose
+    /// webhook has been deleted or marked broken are marked as failed. Returns the amount of deliveries claimed
+    pub async fn resume_due(&self, limit: i64) -> Result<usize, crate::Error> {
+        let due: Vec<(uuid::Uuid, i32, serde_json::Value, Option<Webhook>)> = {
+            let rows: Vec<(uuid::Uuid, Option<uuid::Uuid>, i32, serde_json::Value)> = sqlx::query_as(
+                "UPDATE webhook_logs SET next_attempt_at = NOW() + make_interval(secs => $2)
+                WHERE id IN (
+                    SELECT id FROM webhook_logs WHERE state = 'PENDING' AND next_attempt_at <= NOW()
+                    ORDER BY next_attempt_at LIMIT $1 FOR UPDATE SKIP LOCKED
+                )
+                RETURNING id, webhook_id, tries, data",
+            )
+            .bind(limit)
+            .bind(DELIVERY_LEASE.as_secs_f64())
+            .fetch_all(&self.pool)
+            .await?;
+
+            let mut due = Vec::with_capacity(rows.len());
+            for (log_id, webhook_id, tries, data) in rows {
+                let webhook: Option<Webhook> = match webhook_id {
+                    Some(webhook_id) => sqlx::query_as(&format!("SELECT {WEBHOOK_COLS} FROM webhooks WHERE id = $1 AND broken = false"))
+                        .bind(webhook_id)
+                        .fetch_optional(&self.pool)
+                        .await?,
+                    None => None,
+                };
+
+                due.push((log_id, tries, data, webhook));
+            }
+
+            due
+        };
+
+        let claimed = due.len();
+        let attempts = due.into_iter().map(|(log_id, tries, data, webhook)| async move {
+            let res = match webhook {
+                Some(webhook) => self.attempt(log_id, &webhook, &data.to_string(), tries).await.map(|_| ()),
+                None => sqlx::query("UPDATE webhook_logs SET state = 'FAILED' WHERE id = $1")
+                    .bind(log_id)
+                    .execute(&self.pool)
+                    .await
+                    .map(|_| ())
+                    .map_err(crate::Error::from),
+            };
+
+            if let Err(e) = res {
+                log::error!("Failed to resume webhook delivery {log_id}: {e}");
+            }
+        });
+
+        futures::future::join_all(attempts).await;
+
+        Ok(claimed)
+    }
+
+    /// Polls for pending deliveries that are due (retries, and first attempts interrupted by a restart) and attempts them
+    pub fn spawn_retry_poller(self, poll_interval: Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(poll_interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+            loop {
+                interval.tick().await;
+
+                if let Err(e) = self.resume_due(WEBHOOK_BATCH_SIZE).await {
+                    log::error!("Failed to resume pending webhook deliveries: {e}");
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::HeaderMap, http::StatusCode, routing::post, Router};
+
+    async fn stand_in(headers: HeaderMap, body: String) -> StatusCode {
+        let timestamp: i64 = headers[TIMESTAMP_HEADER].to_str().unwrap().parse().unwrap();
+        let signature = headers[SIGNATURE_HEADER].to_str().unwrap();
+
+        if signature == sign("secret", timestamp, &body) {
+            StatusCode::NO_CONTENT
+        } else {
+            StatusCode::UNAUTHORIZED
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            max_tries: 5,
+            base_delay: Duration::from_secs(1),
+            max_failed_requests: 10,
+        };
+
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff(4), Duration::from_secs(8));
+    }
+
+    #[tokio::test]
+    async fn test_send_once_signed() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, Router::new().route("/", post(stand_in))).await.unwrap();
+        });
+
+        let client = reqwest::Client::new();
+        let url = format!("http://{addr}/");
+
+        let attempt = send_once(&client, &url, "secret", false, r#"{"type":"NewVote"}"#).await.unwrap();
+        assert!(attempt.is_success());
+
+        let attempt = send_once(&client, &url, "wrong", false, r#"{"type":"NewVote"}"#).await.unwrap();
+        assert_eq!(attempt.status_code, 401);
+        assert!(!attempt.is_retryable());
+    }
+}
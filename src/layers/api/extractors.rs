@@ -8,6 +8,13 @@ use axum::Json;
 /// from the DB and if so, returns the user id
 pub struct AuthorizedSession(SessionPermit);
 
+impl AuthorizedSession {
+    /// Returns the permit of the session. This is always a SessionPermit::Success
+    pub fn permit(&self) -> &SessionPermit {
+        &self.0
+    }
+}
+
 impl FromRequestParts<AppData> for AuthorizedSession {
     type Rejection = ApiResponseError;
 
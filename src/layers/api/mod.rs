@@ -2,18 +2,15 @@ pub mod server;
 pub mod extractors;
 pub mod public_api;
 
-use std::rc::Rc;
-use crate::service::lua::{RuntimeCreateOpts, Vm};
-use crate::service::layer::{DispatchLayerResult, Layer, LayerData, NewLayerOpts, SharedLayerData};
+use crate::service::lua::RuntimeCreateOpts;
+use crate::service::layer::{DispatchLayerResult, Layer, LayerRuntime, NewLayerOpts, SharedLayerData};
 use crate::service::axum::Axum;
 use crate::service::sharedlayer::SharedLayer;
-use crate::service::vfs::get_luau_vfs;
 
 
 #[derive(Clone)]
 pub struct ApiLayer {
-    vm: Rc<Vm>,
-    layer_data: LayerData<Self>,
+    runtime: LayerRuntime<Self>,
 }
 
 #[derive(serde::Deserialize, serde::Serialize, Clone)]
@@ -32,24 +29,21 @@ impl Layer for ApiLayer {
 
     async fn new(opts: NewLayerOpts<Self>) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         let shared = SharedLayer::new(opts.pool, opts.diesel);
-        let vm = Self::setup_vm(RuntimeCreateOpts::default(), get_luau_vfs(), None).await?;
+        let axum = Axum::new(server::create(shared.clone()));
+        let config = opts.config;
 
-        let sl = SharedLayerData::new(
-            opts.config, 
-            Axum::new(server::create(shared.clone())),
-            shared
-        );
+        let runtime = LayerRuntime::new(
+            RuntimeCreateOpts::default(),
+            opts.worker,
+            move || SharedLayerData::new(config.clone(), axum.clone(), shared.clone()),
+            "./api",
+        ).await?;
 
-        let layer_data = Self::create_layer_data(sl, &vm)
-        .map_err(|e| format!("Failed to create layer data: {e}"))?;
-
-        Ok(Self {
-            layer_data,
-            vm: Rc::new(vm),
-        })
+        Ok(Self { runtime })
     }
 
     async fn dispatch(&self, msg: Self::Message) -> DispatchLayerResult {
-        Self::dispatch_to_vm_serde(&self.vm, self.layer_data.clone(), msg, "./api").await
+        let (vm, layer_data) = self.runtime.current().await?;
+        Self::dispatch_to_vm_serde(&vm, layer_data, msg, "./api").await
     }
 }
\ No newline at end of file
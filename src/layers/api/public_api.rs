@@ -1,6 +1,21 @@
+use std::collections::HashMap;
+
+use axum::extract::{Path, Query, State};
 use axum::response::IntoResponse;
+use axum::Json;
+use chrono::{DateTime, Utc};
 use reqwest::StatusCode;
 
+use super::extractors::AuthorizedSession;
+use super::server::{ApiError, ApiErrorCode, ApiResponse, ApiResponseError, AppData};
+use crate::entity::{AnyEntityManager, EntityEnumSummaryObject};
+use crate::entity::multipliers::{VoteRules, VoteRulesError};
+use crate::entity::flags::{ChangeFlagsError, FlagAction, flag_names, parse_flag_names};
+use crate::service::health::{self, LayerHealth};
+use crate::service::session::SessionPermit;
+use crate::service::sharedlayer::MAX_VOTE_HISTORY_LIMIT;
+use crate::types::votes::{UserVoteHistoryEntry, UserVoteHistoryFilter, VoteBucket, VoteTimeSeriesError, VoteTimeSeriesPoint};
+
 #[utoipa::path(
     get, 
     tag = "Public API",
@@ -12,4 +27,336 @@ use reqwest::StatusCode;
 // super only exists inside api folder
 pub(super) async fn health_check() -> impl IntoResponse {
     (StatusCode::NO_CONTENT, ())
-}
\ No newline at end of file
+}
+
+#[utoipa::path(
+    get,
+    tag = "Public API",
+    path = "/health-check/layers",
+    responses(
+        (status = 200, body = HashMap<String, Vec<LayerHealth>>),
+    )
+)]
+/// Returns VM crash and restart statistics for each worker of each layer
+pub(super) async fn layer_health() -> Json<HashMap<String, Vec<LayerHealth>>> {
+    Json(health::layer_health())
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+pub(super) struct VoteHistoryQuery {
+    /// Only return votes on entities of this type
+    target_type: Option<String>,
+    /// Whether or not voided votes should be returned
+    include_void: Option<bool>,
+    /// Only return votes created at or after this time
+    from: Option<DateTime<Utc>>,
+    /// Only return votes created before this time
+    to: Option<DateTime<Utc>>,
+    /// The maximum amount of votes to return
+    limit: Option<i64>,
+    /// The amount of votes to skip
+    offset: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    tag = "Public API",
+    path = "/votes/@me",
+    params(VoteHistoryQuery),
+    security(("PublicAuth" = [])),
+    responses(
+        (status = 200, body = Vec<UserVoteHistoryEntry<EntityEnumSummaryObject>>),
+        (status = 400, body = ApiError),
+        (status = 404, body = ApiError),
+    )
+)]
+/// Returns the votes made by the authorized user across all entities, newest first
+pub(super) async fn get_my_votes(
+    State(data): State<AppData>,
+    session: AuthorizedSession,
+    Query(query): Query<VoteHistoryQuery>,
+) -> ApiResponse<Vec<UserVoteHistoryEntry<EntityEnumSummaryObject>>> {
+    let SessionPermit::Success { session, .. } = session.permit() else {
+        unreachable!("AuthorizedSession always holds a successful permit");
+    };
+
+    if session.target_type != "user" {
+        return Err(bad_request("Only user sessions have a vote history".to_string()));
+    }
+
+    if let Some(target_type) = &query.target_type {
+        require_target_type(&data, target_type)?;
+    }
+
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from >= to {
+            return Err(bad_request("from must be before to".to_string()));
+        }
+    }
+
+    let limit = query.limit.unwrap_or(MAX_VOTE_HISTORY_LIMIT);
+    if !(1..=MAX_VOTE_HISTORY_LIMIT).contains(&limit) {
+        return Err(bad_request(format!("limit must be between 1 and {MAX_VOTE_HISTORY_LIMIT}")));
+    }
+
+    let offset = query.offset.unwrap_or(0);
+    if offset < 0 {
+        return Err(bad_request("offset cannot be negative".to_string()));
+    }
+
+    let filter = UserVoteHistoryFilter {
+        target_type: query.target_type,
+        include_void: query.include_void.unwrap_or(false),
+        from: query.from,
+        to: query.to,
+    };
+
+    let votes = data
+        .shared_layer
+        .fetch_user_votes(
+            &session.target_id,
+            filter,
+            limit,
+            offset,
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(ApiError::from(e.to_string()))))?;
+
+    Ok(Json(votes))
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+pub(super) struct VoteTimeSeriesQuery {
+    /// The size of each bucket: hour, day or week
+    bucket: String,
+    /// The start of the range
+    from: DateTime<Utc>,
+    /// The end of the range (exclusive)
+    to: DateTime<Utc>,
+}
+
+#[utoipa::path(
+    get,
+    tag = "Public API",
+    path = "/entities/{target_type}/{target_id}/votes/timeseries",
+    params(
+        ("target_type" = String, Path, description = "The type of the entity"),
+        ("target_id" = String, Path, description = "The ID of the entity"),
+        VoteTimeSeriesQuery,
+    ),
+    responses(
+        (status = 200, body = Vec<VoteTimeSeriesPoint>),
+        (status = 400, body = ApiError),
+        (status = 404, body = ApiError),
+    )
+)]
+/// Returns the upvotes and downvotes an entity received in each bucket of a time range
+pub(super) async fn get_vote_time_series(
+    State(data): State<AppData>,
+    Path((target_type, target_id)): Path<(String, String)>,
+    Query(query): Query<VoteTimeSeriesQuery>,
+) -> ApiResponse<Vec<VoteTimeSeriesPoint>> {
+    let manager = require_target_type(&data, &target_type)?;
+
+    let bucket: VoteBucket = query.bucket.parse().map_err(bad_request)?;
+
+    let points = manager
+        .vote_time_series(&target_id, bucket, query.from, query.to)
+        .await
+        .map_err(|e| match e.downcast_ref::<VoteTimeSeriesError>() {
+            Some(_) => bad_request(e.to_string()),
+            None => (StatusCode::INTERNAL_SERVER_ERROR, Json(ApiError::from(e.to_string()))),
+        })?;
+
+    Ok(Json(points))
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(super) struct ChangeFlagsRequest {
+    /// Whether to force the flags on or off
+    action: FlagAction,
+    /// The names of the flags to change, e.g. BANNED
+    flags: Vec<String>,
+    /// Why the flags are being changed, this is logged
+    reason: String,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub(super) struct ChangeFlagsResponse {
+    /// The resolved flags of the entity after the change
+    flags: Vec<String>,
+}
+
+#[utoipa::path(
+    post,
+    tag = "Public API",
+    path = "/entities/{target_type}/{target_id}/flags",
+    params(
+        ("target_type" = String, Path, description = "The type of the entity"),
+        ("target_id" = String, Path, description = "The ID of the entity"),
+    ),
+    request_body = ChangeFlagsRequest,
+    security(("PublicAuth" = [])),
+    responses(
+        (status = 200, body = ChangeFlagsResponse),
+        (status = 400, body = ApiError),
+        (status = 403, body = ApiError),
+        (status = 404, body = ApiError),
+    )
+)]
+/// Sets or clears flags on an entity. The authorized user must have the matching entity_flags staff permission
+pub(super) async fn change_entity_flags(
+    State(data): State<AppData>,
+    session: AuthorizedSession,
+    Path((target_type, target_id)): Path<(String, String)>,
+    Json(req): Json<ChangeFlagsRequest>,
+) -> ApiResponse<ChangeFlagsResponse> {
+    let actor_id = require_user_id(&session)?;
+
+    let flags = parse_flag_names(&req.flags).map_err(|e| bad_request(e.to_string()))?;
+
+    let flags = data
+        .shared_layer
+        .change_entity_flags(&target_type, &target_id, req.action, flags, actor_id, &req.reason)
+        .await
+        .map_err(|e| match e.downcast_ref::<ChangeFlagsError>() {
+            Some(ChangeFlagsError::MissingPerm(_)) => (
+                StatusCode::FORBIDDEN,
+                Json(ApiError {
+                    message: e.to_string(),
+                    code: ApiErrorCode::Restricted,
+                }),
+            ),
+            Some(ChangeFlagsError::UnknownTargetType(_) | ChangeFlagsError::EntityNotFound(..)) => not_found(e.to_string()),
+            Some(ChangeFlagsError::MissingReason) => bad_request(e.to_string()),
+            None => (StatusCode::INTERNAL_SERVER_ERROR, Json(ApiError::from(e.to_string()))),
+        })?;
+
+    Ok(Json(ChangeFlagsResponse {
+        flags: flag_names(flags),
+    }))
+}
+
+/// Returns the ID of the user the session belongs to, staff actions cannot be performed by other sessions
+fn require_user_id(session: &AuthorizedSession) -> Result<&str, ApiResponseError> {
+    let SessionPermit::Success { session, .. } = session.permit() else {
+        unreachable!("AuthorizedSession always holds a successful permit");
+    };
+
+    if session.target_type != "user" {
+        return Err(bad_request("Only user sessions can perform staff actions".to_string()));
+    }
+
+    Ok(&session.target_id)
+}
+
+/// Returns the entity manager for the target type, or a 404 if it is not a known entity type
+fn require_target_type(data: &AppData, target_type: &str) -> Result<AnyEntityManager, ApiResponseError> {
+    data.shared_layer
+        .entity_manager_for(target_type)
+        .ok_or_else(|| not_found(format!("Unknown target type: {target_type}")))
+}
+
+/// A 400 response with the given message
+fn bad_request(message: String) -> ApiResponseError {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ApiError {
+            message,
+            code: ApiErrorCode::BadRequest,
+        }),
+    )
+}
+
+/// A 404 response with the given message
+fn not_found(message: String) -> ApiResponseError {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ApiError {
+            message,
+            code: ApiErrorCode::NotFound,
+        }),
+    )
+}
+
+#[utoipa::path(
+    put,
+    tag = "Public API",
+    path = "/vote-rules/{target_type}",
+    params(
+        ("target_type" = String, Path, description = "The type of entity the rules apply to"),
+    ),
+    request_body = VoteRules,
+    security(("PublicAuth" = [])),
+    responses(
+        (status = 200, body = VoteRules),
+        (status = 400, body = ApiError),
+        (status = 403, body = ApiError),
+        (status = 404, body = ApiError),
+    )
+)]
+/// Replaces the vote rules of a target type, overriding the config. The authorized user must have the vote_rules.set staff permission
+pub(super) async fn set_target_vote_rules(
+    State(data): State<AppData>,
+    session: AuthorizedSession,
+    Path(target_type): Path<String>,
+    Json(rules): Json<VoteRules>,
+) -> ApiResponse<VoteRules> {
+    let actor_id = require_user_id(&session)?;
+
+    let rules = data
+        .shared_layer
+        .set_vote_rules(&target_type, rules, actor_id)
+        .await
+        .map_err(vote_rules_error)?;
+
+    Ok(Json(rules))
+}
+
+#[utoipa::path(
+    delete,
+    tag = "Public API",
+    path = "/vote-rules/{target_type}",
+    params(
+        ("target_type" = String, Path, description = "The type of entity the rules apply to"),
+    ),
+    security(("PublicAuth" = [])),
+    responses(
+        (status = 200, body = VoteRules),
+        (status = 400, body = ApiError),
+        (status = 403, body = ApiError),
+        (status = 404, body = ApiError),
+    )
+)]
+/// Reverts the vote rules of a target type to the ones in the config. The authorized user must have the vote_rules.set staff permission
+pub(super) async fn reset_target_vote_rules(
+    State(data): State<AppData>,
+    session: AuthorizedSession,
+    Path(target_type): Path<String>,
+) -> ApiResponse<VoteRules> {
+    let actor_id = require_user_id(&session)?;
+
+    let rules = data
+        .shared_layer
+        .reset_vote_rules(&target_type, actor_id)
+        .await
+        .map_err(vote_rules_error)?;
+
+    Ok(Json(rules))
+}
+
+/// Maps errors from changing vote rules to 400/403/404, anything else is a 500
+fn vote_rules_error(e: crate::Error) -> ApiResponseError {
+    match e.downcast_ref::<VoteRulesError>() {
+        Some(VoteRulesError::MissingPerm) => (
+            StatusCode::FORBIDDEN,
+            Json(ApiError {
+                message: e.to_string(),
+                code: ApiErrorCode::Restricted,
+            }),
+        ),
+        Some(VoteRulesError::UnknownTargetType(_)) => not_found(e.to_string()),
+        Some(VoteRulesError::Invalid(_)) => bad_request(e.to_string()),
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json(ApiError::from(e.to_string()))),
+    }
+}
@@ -85,6 +85,11 @@ pub fn create(
     // Public routes
     let public_routes = [
         routes!(public_api::health_check),
+        routes!(public_api::layer_health),
+        routes!(public_api::get_my_votes),
+        routes!(public_api::get_vote_time_series),
+        routes!(public_api::change_entity_flags),
+        routes!(public_api::set_target_vote_rules, public_api::reset_target_vote_rules),
     ];
 
     let mut oapi_router = OpenApiRouter::new();
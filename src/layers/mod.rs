@@ -8,60 +8,61 @@ impl mluau::UserData for DummyData {}
 #[macro_export]
 /// Macro to initialize a layer
 macro_rules! layer_init {
-    ($opts:ident) => {
+    ($opts:ident, $entrypoint:literal) => {
         {
             use crate::layers::DummyData;
             let shared = SharedLayer::new($opts.pool, $opts.diesel);
-            let vm = Self::setup_vm(RuntimeCreateOpts::default(), get_luau_vfs(), None).await?;
+            let config = $opts.config;
+            let runtime = LayerRuntime::new(
+                RuntimeCreateOpts::default(),
+                $opts.worker,
+                move || SharedLayerData::new(config.clone(), DummyData {}, shared.clone()),
+                $entrypoint,
+            ).await?;
 
-            let layer_data = Self::create_layer_data(SharedLayerData::new($opts.config, DummyData {}, shared), &vm)
-            .map_err(|e| format!("Failed to create layer data: {e}"))?;
-
-            Ok(Self {
-                layer_data,
-                vm: Rc::new(vm),
-            })
+            Ok(Self { runtime })
         }
     };
 }
 
 /// Macro to create a layer
+///
+/// An optional `route_key = <fn(&Message) -> Option<String>>` after the layer sets Layer::route_key
 #[macro_export]
 macro_rules! layer {
     // Entry point using default dispatch implementation
-    ($(#[$attr:meta])* $name:ident = ( $mod:ident, $id:literal, $msg_type:ty, $config_type:ty, $entrypoint:literal ) ) => {
+    ($(#[$attr:meta])* $name:ident = ( $mod:ident, $id:literal, $msg_type:ty, $config_type:ty, $entrypoint:literal ) $(, route_key = $route_key:expr)? ) => {
         $crate::layer! {
             @impl
             $(#[$attr])*
-            $name = ( $mod, $id, $msg_type, $config_type, async |self_ref, msg| {
-                Self::dispatch_to_vm_serde(&self_ref.vm, self_ref.layer_data.clone(), msg, $entrypoint).await
-            })
+            $name = ( $mod, $id, $msg_type, $config_type, $entrypoint, async |self_ref, msg| {
+                let (vm, layer_data) = self_ref.runtime.current().await?;
+                Self::dispatch_to_vm_serde(&vm, layer_data, msg, $entrypoint).await
+            }) $(, route_key = $route_key)?
         }
     };
 
     // Entry point with custom dispatch code
-    ($(#[$attr:meta])* $name:ident = ( $mod:ident, $id:literal, $msg_type:ty, $config_type:ty, $entrypoint:literal, async |$self:ident, $msg:ident| $code:block ) ) => {
+    ($(#[$attr:meta])* $name:ident = ( $mod:ident, $id:literal, $msg_type:ty, $config_type:ty, $entrypoint:literal, async |$self:ident, $msg:ident| $code:block ) $(, route_key = $route_key:expr)? ) => {
         $crate::layer! {
             @impl
             $(#[$attr])*
-            $name = ( $mod, $id, $msg_type, $config_type, async |$self, $msg| $code )
+            $name = ( $mod, $id, $msg_type, $config_type, $entrypoint, async |$self, $msg| $code ) $(, route_key = $route_key)?
         }
     };
 
     (@impl 
-        $(#[$attr:meta])* $name:ident = ( $mod:ident, $id:literal, $msg_type:ty, $config_type:ty, async |$self:ident, $msg:ident| $code:expr ) 
+        $(#[$attr:meta])* $name:ident = ( $mod:ident, $id:literal, $msg_type:ty, $config_type:ty, $entrypoint:literal, async |$self:ident, $msg:ident| $code:expr ) $(, route_key = $route_key:expr)?
     ) => {
         pub mod $mod {
             use super::{$msg_type, $config_type};
-            use std::rc::Rc;
-            use crate::service::{layer::{DispatchLayerResult, Layer, LayerData, SharedLayerData, NewLayerOpts}, lua::{Vm, RuntimeCreateOpts}, sharedlayer::SharedLayer, vfs::get_luau_vfs};
+            use crate::service::{layer::{DispatchLayerResult, Layer, LayerRuntime, SharedLayerData, NewLayerOpts}, lua::RuntimeCreateOpts, sharedlayer::SharedLayer};
             use crate::layers::DummyData;
 
             #[derive(Clone)]
             $(#[$attr])*
             pub struct $name {
-                vm: Rc<Vm>,
-                layer_data: LayerData<Self>,
+                runtime: LayerRuntime<Self>,
             }
 
             impl Layer for $name {
@@ -74,7 +75,7 @@ macro_rules! layer {
                 }
 
                 async fn new(opts: NewLayerOpts<Self>) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-                    $crate::layer_init!(opts)
+                    $crate::layer_init!(opts, $entrypoint)
                 }
 
                 async fn dispatch(&self, msg: Self::Message) -> DispatchLayerResult {
@@ -84,6 +85,12 @@ macro_rules! layer {
                     
                     action(self, msg).await
                 }
+
+                $(
+                    fn route_key(msg: &Self::Message) -> Option<String> {
+                        ($route_key)(msg)
+                    }
+                )?
             }
         }
     };
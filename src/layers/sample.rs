@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
+use crate::entity::events::VoteCast;
 use crate::layer;
+use crate::types::votes::VoteReminder;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct SampleLayerConfig {
@@ -11,6 +13,32 @@ pub struct SampleLayerConfig {
 pub enum SampleLayerEvent {
     Startup {},
     TestEvent { data: String },
+    VoteCast(VoteCast),
+    VoteReminder(VoteReminder),
+}
+
+impl From<VoteCast> for SampleLayerEvent {
+    fn from(event: VoteCast) -> Self {
+        SampleLayerEvent::VoteCast(event)
+    }
+}
+
+impl From<VoteReminder> for SampleLayerEvent {
+    fn from(reminder: VoteReminder) -> Self {
+        SampleLayerEvent::VoteReminder(reminder)
+    }
+}
+
+impl SampleLayerEvent {
+    /// Vote events are keyed on the entity so that in a sticky pool every event of an entity is handled
+    /// in order by the same worker
+    pub fn route_key(&self) -> Option<String> {
+        match self {
+            SampleLayerEvent::VoteCast(event) => Some(format!("{}:{}", event.target_type, event.target_id)),
+            SampleLayerEvent::VoteReminder(reminder) => Some(format!("{}:{}", reminder.target_type, reminder.target_id)),
+            SampleLayerEvent::Startup {} | SampleLayerEvent::TestEvent { .. } => None,
+        }
+    }
 }
 
 impl Default for SampleLayerEvent {
@@ -24,5 +52,6 @@ layer! {
     ///
     /// Layers may be used for any services for the Omniplex bot list such as html sanitization, cache server/presence
     /// handling etc.
-    SampleLayer = ( samplelayer, "samplelayer", SampleLayerEvent, SampleLayerConfig, "./samplelayer" )
+    SampleLayer = ( samplelayer, "samplelayer", SampleLayerEvent, SampleLayerConfig, "./samplelayer" ),
+    route_key = SampleLayerEvent::route_key
 }
\ No newline at end of file
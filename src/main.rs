@@ -1,6 +1,9 @@
+use std::collections::HashMap;
+
 use sqlx::postgres::PgPoolOptions;
 
-use crate::{layers::sample::SampleLayerEvent, service::layer::{Layer, NewLayerOpts}};
+use crate::entity::webhooks::{DEFAULT_WEBHOOK_POLL_INTERVAL, WebhookDispatcher};
+use crate::service::supervisor::{DEFAULT_SHUTDOWN_DEADLINE, LayerSettings, LayerSupervisor};
 
 pub(crate) mod service;
 pub mod entity;
@@ -17,6 +20,16 @@ pub type Db = diesel_async::pooled_connection::bb8::Pool<diesel_async::AsyncPgCo
 struct BaseConfig {
     postgres_url: String,
     max_db_connections: u32,
+    /// The layers to start, in order. Each layer is configured by the top-level key with its name
+    #[serde(default = "default_layers")]
+    layers: Vec<String>,
+    /// Queue, timeout and worker settings per layer name
+    #[serde(default)]
+    layer_settings: HashMap<String, LayerSettings>,
+}
+
+fn default_layers() -> Vec<String> {
+    vec!["samplelayer".to_string()]
 }
 
 #[tokio::main]
@@ -46,12 +59,17 @@ async fn main() {
             .expect("Failed to create diesel pool")
     };
 
-    // Load up SampleLayer
-    let th = layers::sample::samplelayer::SampleLayer::load(NewLayerOpts {
-        config: serde_json::from_value(config["sample"].clone()).expect("Failed to deserialize config"),
-        diesel,
-        pool,
-    });
+    // Resume webhook deliveries left pending by a previous run, and keep retrying failed ones
+    WebhookDispatcher::new(pool.clone()).spawn_retry_poller(DEFAULT_WEBHOOK_POLL_INTERVAL);
+
+    let mut supervisor = LayerSupervisor::new(pool, diesel);
+    supervisor
+        .start_from_config(&base_config.layers, &base_config.layer_settings, &config)
+        .await
+        .expect("Failed to start layers");
 
-    th.dispatch(SampleLayerEvent::default()).await.expect("Failed to dispatch event");
+    if let Err(e) = supervisor.run_until_shutdown(DEFAULT_SHUTDOWN_DEADLINE).await {
+        log::error!("Unclean shutdown: {e}");
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,59 @@
+use crate::migrations::Migration;
+
+const ENTITY_FLAGS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS entity_flags (
+    target_id TEXT NOT NULL,
+    target_type TEXT NOT NULL,
+    -- Bits forced on, applied on top of the flags of the entity type
+    flags_set BIGINT NOT NULL DEFAULT 0,
+    -- Bits forced off, applied after flags_set
+    flags_cleared BIGINT NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (target_id, target_type)
+)
+"#;
+
+const ENTITY_FLAG_LOGS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS entity_flag_logs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    target_id TEXT NOT NULL,
+    target_type TEXT NOT NULL,
+    -- The user who made the change
+    actor_id TEXT NOT NULL,
+    -- Either 'set' or 'clear'
+    action TEXT NOT NULL,
+    -- The bits that were set or cleared
+    flags BIGINT NOT NULL,
+    -- The resolved flags of the entity before and after the change
+    old_flags BIGINT NOT NULL,
+    new_flags BIGINT NOT NULL,
+    reason TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+)
+"#;
+
+pub static MIGRATION: Migration = Migration {
+    id: "add_entity_flags",
+    description: "Add entity_flags and entity_flag_logs tables for staff-editable entity flags",
+    up: |pool| {
+        Box::pin(async move {
+            let mut tx = pool.begin().await?;
+
+            let stmts: [&str; _] = [
+                ENTITY_FLAGS_TABLE,
+                ENTITY_FLAG_LOGS_TABLE,
+                "CREATE INDEX IF NOT EXISTS entity_flag_logs_target_idx ON entity_flag_logs (target_id, target_type, created_at DESC)",
+            ];
+
+            for stmt in stmts.iter() {
+                sqlx::query(stmt)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+
+            tx.commit().await?;
+
+            Ok(())
+        })
+    },
+};
@@ -0,0 +1,23 @@
+use crate::migrations::Migration;
+
+pub static MIGRATION: Migration = Migration {
+    id: "add_entity_timezones",
+    description: "Add an optional IANA timezone to known_entities, used by weekend vote rules",
+    up: |pool| {
+        Box::pin(async move {
+            let mut tx = pool.begin().await?;
+
+            let stmts: [&str; _] = ["ALTER TABLE known_entities ADD COLUMN IF NOT EXISTS timezone TEXT"];
+
+            for stmt in stmts.iter() {
+                sqlx::query(stmt)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+
+            tx.commit().await?;
+
+            Ok(())
+        })
+    },
+};
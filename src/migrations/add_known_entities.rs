@@ -1,7 +1,8 @@
 use crate::migrations::Migration;
 
+/// known_entities may already exist (see new_schema.sql), so every statement here must be idempotent
 const KNOWN_ENTITIES_TABLE: &str = r#"
-CREATE TABLE known_entities (
+CREATE TABLE IF NOT EXISTS known_entities (
     target_id TEXT NOT NULL, 
     target_type TEXT NOT NULL,
     PRIMARY KEY (target_id, target_type),
@@ -27,9 +28,39 @@ CREATE TABLE known_entities (
 )
 "#;
 
+/// Same as _bot_fk/_user_fk but for servers
+const KNOWN_ENTITIES_SERVER_FK_COLUMN: &str = r#"
+ALTER TABLE known_entities ADD COLUMN IF NOT EXISTS _server_fk TEXT GENERATED ALWAYS AS (
+    CASE WHEN target_type = 'server' THEN target_id ELSE NULL END
+) STORED
+"#;
+
+// Team IDs are UUIDs so teams cannot have a FK here, EntityManager::delete removes them instead
+const KNOWN_ENTITIES_SERVER_FK: &str = r#"
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_constraint WHERE conname = 'fk_known_servers') THEN
+        ALTER TABLE known_entities ADD CONSTRAINT fk_known_servers
+            FOREIGN KEY (_server_fk) REFERENCES servers(server_id)
+            ON DELETE CASCADE;
+    END IF;
+END
+$$
+"#;
+
+/// Registers all entities that existed before known_entities
+const KNOWN_ENTITIES_BACKFILL: &str = r#"
+INSERT INTO known_entities (target_id, target_type)
+SELECT bot_id, 'bot' FROM bots
+UNION ALL SELECT user_id, 'user' FROM users
+UNION ALL SELECT server_id, 'server' FROM servers
+UNION ALL SELECT id::TEXT, 'team' FROM teams
+ON CONFLICT DO NOTHING
+"#;
+
 pub static MIGRATION: Migration = Migration {
-    id: "add_entity_approx_votes",
-    description: "Add new entity_approx_votes table",
+    id: "add_known_entities",
+    description: "Add known_entities table and register existing entities",
     up: |pool| {
         Box::pin(async move {
             let mut tx = pool.begin().await?;
@@ -37,6 +68,9 @@ pub static MIGRATION: Migration = Migration {
             // TODO: Add actual statements here
             let stmts: [&str; _] = [
                 KNOWN_ENTITIES_TABLE,
+                KNOWN_ENTITIES_SERVER_FK_COLUMN,
+                KNOWN_ENTITIES_SERVER_FK,
+                KNOWN_ENTITIES_BACKFILL,
             ];
 
             for stmt in stmts.iter() {
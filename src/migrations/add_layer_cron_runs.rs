@@ -0,0 +1,44 @@
+use crate::migrations::Migration;
+
+const LAYER_CRON_RUNS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS layer_cron_runs (
+    layer TEXT NOT NULL,
+    job TEXT NOT NULL,
+    -- The scheduled time of the last run, not when it actually ran
+    last_run_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (layer, job)
+)
+"#;
+
+/// The outcome of the last run, recorded once it finishes. Runs that did not succeed are retried
+const LAYER_CRON_RUNS_OUTCOME: &str = r#"
+ALTER TABLE layer_cron_runs
+    ADD COLUMN IF NOT EXISTS last_finished_at TIMESTAMPTZ,
+    -- NULL if the last run succeeded or has not finished yet
+    ADD COLUMN IF NOT EXISTS last_error TEXT
+"#;
+
+pub static MIGRATION: Migration = Migration {
+    id: "add_layer_cron_runs",
+    description: "Add layer_cron_runs table for scheduled layer dispatches",
+    up: |pool| {
+        Box::pin(async move {
+            let mut tx = pool.begin().await?;
+
+            let stmts: [&str; _] = [
+                LAYER_CRON_RUNS_TABLE,
+                LAYER_CRON_RUNS_OUTCOME,
+            ];
+
+            for stmt in stmts.iter() {
+                sqlx::query(stmt)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+
+            tx.commit().await?;
+
+            Ok(())
+        })
+    },
+};
@@ -0,0 +1,56 @@
+use crate::migrations::Migration;
+
+const VOTE_CREDIT_TIERS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS vote_credit_tiers (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    target_type TEXT NOT NULL,
+    -- Lower positions are applied first
+    position INTEGER NOT NULL,
+    -- The amount of votes the tier covers, 0 covers all remaining votes
+    votes INTEGER NOT NULL,
+    -- Cents per vote
+    cents DOUBLE PRECISION NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (target_type, position)
+)
+"#;
+
+const VOTE_REDEEM_LOGS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS entity_vote_redeem_logs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    target_id TEXT NOT NULL,
+    target_type TEXT NOT NULL,
+    credits INTEGER NOT NULL,
+    redeemed_credits INTEGER NOT NULL DEFAULT 0,
+    redeemed_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+)
+"#;
+
+pub static MIGRATION: Migration = Migration {
+    id: "add_vote_credits",
+    description: "Add vote_credit_tiers and entity_vote_redeem_logs tables for vote credit redemption",
+    up: |pool| {
+        Box::pin(async move {
+            let mut tx = pool.begin().await?;
+
+            let stmts: [&str; _] = [
+                VOTE_CREDIT_TIERS_TABLE,
+                VOTE_REDEEM_LOGS_TABLE,
+                "CREATE INDEX IF NOT EXISTS entity_vote_redeem_logs_target_idx ON entity_vote_redeem_logs (target_id, target_type)",
+                // Votes stamped by a redemption point at its log
+                "ALTER TABLE entity_votes ADD COLUMN IF NOT EXISTS credit_redeem UUID REFERENCES entity_vote_redeem_logs (id) ON DELETE SET NULL",
+            ];
+
+            for stmt in stmts.iter() {
+                sqlx::query(stmt)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+
+            tx.commit().await?;
+
+            Ok(())
+        })
+    },
+};
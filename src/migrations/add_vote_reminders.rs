@@ -0,0 +1,40 @@
+use crate::migrations::Migration;
+
+const VOTE_REMINDERS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS entity_vote_reminders (
+    user_id TEXT NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    target_id TEXT NOT NULL,
+    target_type TEXT NOT NULL,
+    -- When the user can vote again. NULL if the user has not voted since the last reminder
+    due_at TIMESTAMPTZ,
+    last_fired_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, target_id, target_type)
+)
+"#;
+
+pub static MIGRATION: Migration = Migration {
+    id: "add_vote_reminders",
+    description: "Add entity_vote_reminders table for vote reminders",
+    up: |pool| {
+        Box::pin(async move {
+            let mut tx = pool.begin().await?;
+
+            let stmts: [&str; _] = [
+                VOTE_REMINDERS_TABLE,
+                "CREATE INDEX IF NOT EXISTS entity_vote_reminders_due_idx ON entity_vote_reminders (due_at) WHERE due_at IS NOT NULL",
+                "CREATE INDEX IF NOT EXISTS entity_vote_reminders_target_idx ON entity_vote_reminders (target_id, target_type)",
+            ];
+
+            for stmt in stmts.iter() {
+                sqlx::query(stmt)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+
+            tx.commit().await?;
+
+            Ok(())
+        })
+    },
+};
@@ -0,0 +1,51 @@
+use crate::migrations::Migration;
+
+const VOTE_RESETS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS entity_vote_resets (
+    period TEXT NOT NULL,
+    target_type TEXT NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- NULL while the reset is still running (or has crashed and needs to be resumed)
+    completed_at TIMESTAMPTZ,
+    PRIMARY KEY (period, target_type)
+)
+"#;
+
+const VOTE_RESET_HISTORY_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS entity_vote_reset_history (
+    period TEXT NOT NULL,
+    target_id TEXT NOT NULL,
+    target_type TEXT NOT NULL,
+    -- The final vote count of the entity at the time of the reset
+    votes INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (period, target_id, target_type),
+    FOREIGN KEY (period, target_type) REFERENCES entity_vote_resets (period, target_type) ON DELETE CASCADE
+)
+"#;
+
+pub static MIGRATION: Migration = Migration {
+    id: "add_vote_resets",
+    description: "Add entity_vote_resets and entity_vote_reset_history tables for periodic vote resets",
+    up: |pool| {
+        Box::pin(async move {
+            let mut tx = pool.begin().await?;
+
+            let stmts: [&str; _] = [
+                VOTE_RESETS_TABLE,
+                VOTE_RESET_HISTORY_TABLE,
+                "CREATE INDEX IF NOT EXISTS entity_vote_reset_history_target_idx ON entity_vote_reset_history (target_id, target_type)",
+            ];
+
+            for stmt in stmts.iter() {
+                sqlx::query(stmt)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+
+            tx.commit().await?;
+
+            Ok(())
+        })
+    },
+};
@@ -0,0 +1,35 @@
+use crate::migrations::Migration;
+
+const VOTE_RULES_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS entity_vote_rules (
+    -- Target types without a row here use the vote rules in the config
+    target_type TEXT PRIMARY KEY,
+    -- Serialized VoteRules
+    rules JSONB NOT NULL,
+    -- The staff member who last changed the rules
+    updated_by TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+)
+"#;
+
+pub static MIGRATION: Migration = Migration {
+    id: "add_vote_rules",
+    description: "Add entity_vote_rules table storing vote rules changed by staff",
+    up: |pool| {
+        Box::pin(async move {
+            let mut tx = pool.begin().await?;
+
+            let stmts: [&str; _] = [VOTE_RULES_TABLE];
+
+            for stmt in stmts.iter() {
+                sqlx::query(stmt)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+
+            tx.commit().await?;
+
+            Ok(())
+        })
+    },
+};
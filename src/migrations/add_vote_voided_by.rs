@@ -0,0 +1,24 @@
+use crate::migrations::Migration;
+
+pub static MIGRATION: Migration = Migration {
+    id: "add_vote_voided_by",
+    description: "Record the staff member who voided a vote",
+    up: |pool| {
+        Box::pin(async move {
+            let mut tx = pool.begin().await?;
+
+            // NULL for votes voided by the system (e.g. vote resets)
+            let stmts: [&str; _] = ["ALTER TABLE entity_votes ADD COLUMN IF NOT EXISTS voided_by TEXT"];
+
+            for stmt in stmts.iter() {
+                sqlx::query(stmt)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+
+            tx.commit().await?;
+
+            Ok(())
+        })
+    },
+};
@@ -0,0 +1,70 @@
+use crate::migrations::Migration;
+
+const WEBHOOKS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS webhooks (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    target_id TEXT NOT NULL,
+    target_type TEXT NOT NULL,
+    name TEXT NOT NULL,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    simple_auth BOOLEAN NOT NULL DEFAULT false,
+    -- An empty list means all events
+    event_whitelist TEXT[] NOT NULL DEFAULT '{}',
+    broken BOOLEAN NOT NULL DEFAULT false,
+    failed_requests INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+)
+"#;
+
+const WEBHOOK_LOGS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS webhook_logs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    target_id TEXT NOT NULL,
+    target_type TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    url TEXT NOT NULL,
+    data JSONB NOT NULL,
+    -- PENDING until the delivery succeeds (SUCCESS) or runs out of retries (FAILED)
+    state TEXT NOT NULL DEFAULT 'PENDING',
+    tries INTEGER NOT NULL DEFAULT 0,
+    last_try TIMESTAMPTZ,
+    status_code INTEGER,
+    response TEXT,
+    request_headers JSONB,
+    response_headers JSONB,
+    bad_intent BOOLEAN NOT NULL DEFAULT false,
+    webhook_id UUID REFERENCES webhooks (id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+)
+"#;
+
+pub static MIGRATION: Migration = Migration {
+    id: "add_webhooks",
+    description: "Add webhooks and webhook_logs tables, with persisted retry scheduling for deliveries",
+    up: |pool| {
+        Box::pin(async move {
+            let mut tx = pool.begin().await?;
+
+            let stmts: [&str; _] = [
+                WEBHOOKS_TABLE,
+                WEBHOOK_LOGS_TABLE,
+                // When the next delivery attempt is due, pending deliveries are resumed from here after a restart
+                "ALTER TABLE webhook_logs ADD COLUMN IF NOT EXISTS next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW()",
+                "CREATE INDEX IF NOT EXISTS webhooks_target_idx ON webhooks (target_id, target_type)",
+                "CREATE INDEX IF NOT EXISTS webhook_logs_target_idx ON webhook_logs (target_id, target_type)",
+                "CREATE INDEX IF NOT EXISTS webhook_logs_pending_idx ON webhook_logs (next_attempt_at) WHERE state = 'PENDING'",
+            ];
+
+            for stmt in stmts.iter() {
+                sqlx::query(stmt)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+
+            tx.commit().await?;
+
+            Ok(())
+        })
+    },
+};
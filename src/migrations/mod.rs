@@ -1,6 +1,15 @@
 mod add_pkeys;
 mod add_entity_approx_votes;
 mod add_known_entities;
+mod add_vote_resets;
+mod add_entity_flags;
+mod add_vote_reminders;
+mod add_layer_cron_runs;
+mod add_vote_credits;
+mod add_webhooks;
+mod add_vote_voided_by;
+mod add_entity_timezones;
+mod add_vote_rules;
 
 use futures::future::BoxFuture;
 use log::info;
@@ -12,9 +21,19 @@ pub struct Migration {
     pub up: fn(sqlx::Pool<sqlx::Postgres>) -> BoxFuture<'static, Result<(), crate::Error>>,
 }
 
-pub const MIGRATIONS: [Migration; 2] = [
+pub const MIGRATIONS: [Migration; 12] = [
     add_pkeys::MIGRATION,
     add_entity_approx_votes::MIGRATION,
+    add_known_entities::MIGRATION,
+    add_vote_resets::MIGRATION,
+    add_entity_flags::MIGRATION,
+    add_vote_reminders::MIGRATION,
+    add_layer_cron_runs::MIGRATION,
+    add_vote_credits::MIGRATION,
+    add_webhooks::MIGRATION,
+    add_vote_voided_by::MIGRATION,
+    add_entity_timezones::MIGRATION,
+    add_vote_rules::MIGRATION,
 ];
 
 pub async fn apply_migrations(pool: sqlx::PgPool) -> Result<(), crate::Error> {
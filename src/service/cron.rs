@@ -0,0 +1,224 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{ConnectOptions, Connection};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A message dispatched to a layer on a cron schedule, declared in the layer's settings
+pub struct ScheduledDispatch {
+    /// The name of the job, unique within the layer
+    pub name: String,
+    /// The cron expression, with a leading seconds field (e.g. `0 */5 * * * *` for every 5 minutes). Times are UTC
+    pub cron: String,
+    /// The message to dispatch, as the JSON form of the layer's message type
+    pub message: serde_json::Value,
+}
+
+/// A parsed ScheduledDispatch
+#[derive(Debug, Clone)]
+pub struct CronJob {
+    pub name: String,
+    pub schedule: ::cron::Schedule,
+    pub message: serde_json::Value,
+}
+
+impl CronJob {
+    pub fn parse(job: &ScheduledDispatch) -> Result<Self, crate::Error> {
+        let schedule = ::cron::Schedule::from_str(&job.cron)
+            .map_err(|e| format!("Invalid cron expression for job {}: {e}", job.name))?;
+
+        Ok(Self {
+            name: job.name.clone(),
+            schedule,
+            message: job.message.clone(),
+        })
+    }
+
+    /// Returns the first scheduled time strictly after the given time
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.schedule.after(&after).next()
+    }
+}
+
+/// How long to wait before retrying a run that failed or was busy on another instance
+///
+/// Runs are not retried once the next scheduled run of the job is due, as that run supersedes them
+pub const CRON_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// The last run of a job stored in layer_cron_runs
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct LastCronRun {
+    /// The scheduled time of the run, not when it actually ran
+    pub last_run_at: DateTime<Utc>,
+    /// None if the run has not finished, which means its instance stopped while running it if the job is not locked
+    pub last_finished_at: Option<DateTime<Utc>>,
+    /// None if the run succeeded or has not finished
+    pub last_error: Option<String>,
+}
+
+impl LastCronRun {
+    fn succeeded(&self) -> bool {
+        self.last_finished_at.is_some() && self.last_error.is_none()
+    }
+
+    /// Returns whether or not the scheduled run at fire_at no longer needs to happen, either because it
+    /// succeeded or because a later run has been started
+    pub fn covers(&self, fire_at: DateTime<Utc>) -> bool {
+        self.last_run_at > fire_at || (self.last_run_at == fire_at && self.succeeded())
+    }
+
+    /// Returns the time scheduling should continue after, this is just before the last run if it
+    /// did not succeed so that it is retried
+    pub fn resume_after(&self) -> DateTime<Utc> {
+        if self.succeeded() {
+            self.last_run_at
+        } else {
+            self.last_run_at - chrono::Duration::milliseconds(1)
+        }
+    }
+}
+
+/// Returns the last run of a job, if it has ever run
+pub async fn fetch_last_run(pool: &sqlx::PgPool, layer: &str, job: &str) -> Result<Option<LastCronRun>, crate::Error> {
+    let last_run: Option<LastCronRun> = sqlx::query_as(
+        "SELECT last_run_at, last_finished_at, last_error FROM layer_cron_runs WHERE layer = $1 AND job = $2",
+    )
+    .bind(layer)
+    .bind(job)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(last_run)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The outcome of run_once
+pub enum CronRunOutcome {
+    /// This instance ran the job
+    Ran,
+    /// The run already succeeded or was superseded by a later run
+    AlreadyRan,
+    /// Another instance is running the job right now
+    Busy,
+}
+
+/// Runs the scheduled run of a job at fire_at, unless it already succeeded or another instance is running the job
+///
+/// The job is locked with a session-level pg_try_advisory_lock held on a dedicated connection for as long as the
+/// job runs. If the instance stops mid-run the connection closes, Postgres releases the lock and the unfinished run
+/// can be picked up by another instance. Failed runs are recorded in last_error and are not considered done
+pub async fn run_once<F, Fut>(
+    pool: &sqlx::PgPool,
+    layer: &str,
+    job: &str,
+    fire_at: DateTime<Utc>,
+    run: F,
+) -> Result<CronRunOutcome, crate::Error>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<(), crate::Error>>,
+{
+    // Not taken from the pool as a pooled connection would keep the lock if this future is dropped mid-run
+    let mut conn = pool.connect_options().connect().await?;
+
+    let locked: bool = sqlx::query_scalar("SELECT pg_try_advisory_lock(hashtextextended($1, 0))")
+        .bind(format!("cron:{layer}:{job}"))
+        .fetch_one(&mut conn)
+        .await?;
+
+    if !locked {
+        return Ok(CronRunOutcome::Busy);
+    }
+
+    let last_run: Option<LastCronRun> = sqlx::query_as(
+        "SELECT last_run_at, last_finished_at, last_error FROM layer_cron_runs WHERE layer = $1 AND job = $2",
+    )
+    .bind(layer)
+    .bind(job)
+    .fetch_optional(&mut conn)
+    .await?;
+
+    if last_run.is_some_and(|last_run| last_run.covers(fire_at)) {
+        return Ok(CronRunOutcome::AlreadyRan);
+    }
+
+    sqlx::query(
+        "INSERT INTO layer_cron_runs (layer, job, last_run_at) VALUES ($1, $2, $3)
+        ON CONFLICT (layer, job) DO UPDATE SET last_run_at = EXCLUDED.last_run_at, last_finished_at = NULL, last_error = NULL",
+    )
+    .bind(layer)
+    .bind(job)
+    .bind(fire_at)
+    .execute(&mut conn)
+    .await?;
+
+    let res = run().await;
+
+    sqlx::query("UPDATE layer_cron_runs SET last_finished_at = NOW(), last_error = $3 WHERE layer = $1 AND job = $2")
+        .bind(layer)
+        .bind(job)
+        .bind(res.as_ref().err().map(|e| e.to_string()))
+        .execute(&mut conn)
+        .await?;
+
+    // Closing the connection releases the lock
+    conn.close().await?;
+
+    res?;
+    Ok(CronRunOutcome::Ran)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cron_job() {
+        let job = CronJob::parse(&ScheduledDispatch {
+            name: "cleanup".to_string(),
+            cron: "0 */5 * * * *".to_string(),
+            message: serde_json::json!({ "type": "Cleanup" }),
+        })
+        .unwrap();
+
+        let after = DateTime::parse_from_rfc3339("2025-01-01T00:03:20Z").unwrap().with_timezone(&Utc);
+        let next = DateTime::parse_from_rfc3339("2025-01-01T00:05:00Z").unwrap().with_timezone(&Utc);
+        assert_eq!(job.next_after(after), Some(next));
+        assert_eq!(job.next_after(next), Some(next + chrono::Duration::minutes(5)));
+
+        assert!(CronJob::parse(&ScheduledDispatch {
+            name: "bad".to_string(),
+            cron: "every tuesday".to_string(),
+            message: serde_json::Value::Null,
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_last_cron_run() {
+        let fire_at = DateTime::parse_from_rfc3339("2025-01-01T00:05:00Z").unwrap().with_timezone(&Utc);
+        let run = |last_run_at, finished: bool, error: Option<&str>| LastCronRun {
+            last_run_at,
+            last_finished_at: finished.then_some(fire_at),
+            last_error: error.map(|e| e.to_string()),
+        };
+
+        // Succeeded
+        let last = run(fire_at, true, None);
+        assert!(last.covers(fire_at));
+        assert_eq!(last.resume_after(), fire_at);
+
+        // Failed or stopped mid-run, so it is retried
+        for last in [run(fire_at, true, Some("boom")), run(fire_at, false, None)] {
+            assert!(!last.covers(fire_at));
+            assert!(last.resume_after() < fire_at);
+        }
+
+        // Superseded by a later run, even if that one failed
+        assert!(run(fire_at + chrono::Duration::minutes(5), true, Some("boom")).covers(fire_at));
+
+        // An earlier run never covers a later one
+        assert!(!run(fire_at - chrono::Duration::minutes(5), true, None).covers(fire_at));
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Health of every layer worker that has reported in, keyed by (layer name, worker index)
+static LAYER_HEALTH: LazyLock<Mutex<HashMap<(String, usize), LayerHealth>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone, Default, Serialize, utoipa::ToSchema)]
+/// Crash and restart statistics for the VMs of one worker of a layer
+pub struct LayerHealth {
+    /// The index of the worker within the layer's pool
+    pub worker: usize,
+    /// Whether the worker's VM is currently broken
+    pub broken: bool,
+    /// How many times the worker's VM has broken
+    pub crashes: u64,
+    /// How many times the worker's broken VM has been recreated
+    pub restarts: u64,
+    /// When the worker's VM last broke
+    pub last_crash_at: Option<DateTime<Utc>>,
+    /// If set, the worker's VM broke too often and will not be restarted before this time
+    pub restart_blocked_until: Option<DateTime<Utc>>,
+}
+
+fn update(layer: &str, worker: usize, f: impl FnOnce(&mut LayerHealth)) {
+    let mut health = LAYER_HEALTH.lock().expect("layer health poisoned");
+    f(health.entry((layer.to_string(), worker)).or_insert_with(|| LayerHealth {
+        worker,
+        ..Default::default()
+    }));
+}
+
+/// Records that the VM of a layer worker is up and running
+pub fn record_running(layer: &str, worker: usize) {
+    update(layer, worker, |h| {
+        h.broken = false;
+        h.restart_blocked_until = None;
+    });
+}
+
+/// Records that the VM of a layer worker has broken
+pub fn record_crash(layer: &str, worker: usize) {
+    update(layer, worker, |h| {
+        h.broken = true;
+        h.crashes += 1;
+        h.last_crash_at = Some(Utc::now());
+    });
+}
+
+/// Records that the broken VM of a layer worker has been recreated
+pub fn record_restart(layer: &str, worker: usize) {
+    update(layer, worker, |h| {
+        h.restarts += 1;
+    });
+    record_running(layer, worker);
+}
+
+/// Records that restarting the VM of a layer worker is suppressed until the given time
+pub fn record_restart_blocked(layer: &str, worker: usize, until: DateTime<Utc>) {
+    update(layer, worker, |h| {
+        h.restart_blocked_until = Some(until);
+    });
+}
+
+/// Returns the health of every worker of every layer, ordered by worker index
+pub fn layer_health() -> HashMap<String, Vec<LayerHealth>> {
+    let mut layers: HashMap<String, Vec<LayerHealth>> = HashMap::new();
+    for ((layer, _), health) in LAYER_HEALTH.lock().expect("layer health poisoned").iter() {
+        layers.entry(layer.clone()).or_default().push(health.clone());
+    }
+
+    for workers in layers.values_mut() {
+        workers.sort_by_key(|h| h.worker);
+    }
+
+    layers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layer_health() {
+        let layer = "test_layer_health";
+        record_running(layer, 0);
+        record_crash(layer, 0);
+        record_restart_blocked(layer, 0, Utc::now());
+
+        let health = layer_health().remove(layer).unwrap().remove(0);
+        assert!(health.broken);
+        assert_eq!(health.crashes, 1);
+        assert!(health.restart_blocked_until.is_some());
+
+        record_restart(layer, 0);
+        let health = layer_health().remove(layer).unwrap().remove(0);
+        assert!(!health.broken);
+        assert_eq!(health.restarts, 1);
+        assert!(health.restart_blocked_until.is_none());
+    }
+
+    #[test]
+    fn test_layer_health_per_worker() {
+        let layer = "test_layer_health_per_worker";
+        record_running(layer, 1);
+        record_running(layer, 0);
+        record_crash(layer, 1);
+
+        // A crash of one worker does not mark the others as broken
+        let workers = layer_health().remove(layer).unwrap();
+        assert_eq!(workers.len(), 2);
+        assert_eq!(workers[0].worker, 0);
+        assert!(!workers[0].broken);
+        assert_eq!(workers[1].worker, 1);
+        assert!(workers[1].broken);
+        assert_eq!(workers[1].crashes, 1);
+    }
+}
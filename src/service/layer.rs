@@ -1,13 +1,18 @@
 use mlua_scheduler::LuaSchedulerAsyncUserData;
 use mluau::prelude::*;
 use serde::{Serialize, de::DeserializeOwned};
+use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use tokio::task::spawn_local;
 use tokio::{
     runtime::LocalOptions,
     select,
     sync::{
-        mpsc::{UnboundedReceiver, UnboundedSender},
+        mpsc::{Receiver, Sender, error::TrySendError},
         oneshot::{Receiver as OneshotReceiver, Sender as OneshotSender, channel},
     },
 };
@@ -15,11 +20,86 @@ use tokio_util::sync::CancellationToken;
 use crate::service::lua::{
     OnBrokenFunc, RuntimeCreateOpts, Vm
 };
+use crate::service::health;
 use crate::service::optional_value::OptionalValue;
+use crate::service::vfs::{LuauSource, get_luau_vfs, luau_generation, luau_source, watched_entries};
 use crate::service::sharedlayer::{LuaSharedLayer, SharedLayer};
 
 pub type DispatchLayerResult = Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>>;
 
+/// The window over which restarts of a broken layer VM are counted
+pub const VM_RESTART_WINDOW: Duration = Duration::from_secs(60);
+/// How many times a broken layer VM may be restarted within VM_RESTART_WINDOW before restarts are suppressed
+pub const MAX_VM_RESTARTS_PER_WINDOW: usize = 5;
+
+/// The default amount of messages that can wait in a layer's queue
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+/// The default time a layer has to handle a message, in seconds
+pub const DEFAULT_DISPATCH_TIMEOUT_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+/// Queue and timeout limits of a layer
+pub struct LayerLimits {
+    /// How many messages can wait in the layer's queue. Once full, dispatch waits for room and try_dispatch fails
+    pub queue_capacity: usize,
+    /// How long the layer has to handle a message, in seconds. The Lua thread handling the message is closed
+    /// once this passes. 0 disables the timeout
+    pub dispatch_timeout_secs: u64,
+}
+
+impl Default for LayerLimits {
+    fn default() -> Self {
+        Self {
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            dispatch_timeout_secs: DEFAULT_DISPATCH_TIMEOUT_SECS,
+        }
+    }
+}
+
+impl LayerLimits {
+    /// Returns the dispatch timeout, None if disabled
+    pub fn dispatch_timeout(&self) -> Option<Duration> {
+        (self.dispatch_timeout_secs > 0).then(|| Duration::from_secs(self.dispatch_timeout_secs))
+    }
+}
+
+#[derive(Debug)]
+/// Errors returned by LayerThread itself (as opposed to the layer) when dispatching a message
+///
+/// These are boxed into the DispatchLayerResult error and can be recovered with downcast_ref
+pub enum LayerDispatchError {
+    /// The layer's queue is full (only returned by try_dispatch)
+    QueueFull,
+    /// The layer did not handle the message within its dispatch timeout
+    Timeout(Duration),
+    /// The layer thread has stopped
+    Stopped,
+}
+
+impl std::fmt::Display for LayerDispatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::QueueFull => write!(f, "Layer queue is full"),
+            Self::Timeout(timeout) => write!(f, "Layer did not handle the message within {}s", timeout.as_secs()),
+            Self::Stopped => write!(f, "Layer thread has stopped"),
+        }
+    }
+}
+
+impl std::error::Error for LayerDispatchError {}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+/// A snapshot of a layer's queue
+pub struct LayerQueueStats {
+    /// Messages waiting to be picked up by the layer
+    pub queued: usize,
+    /// Messages the layer is currently handling
+    pub in_flight: usize,
+    /// The capacity of the layer's queue
+    pub capacity: usize,
+}
+
 #[derive(Clone)]
 /// A wrapper around layer data to be passed to VMs
 pub struct LayerData<L: Layer> {
@@ -84,6 +164,9 @@ pub struct NewLayerOpts<L: Layer> {
     pub config: L::Config,
     pub pool: sqlx::PgPool,
     pub diesel: crate::Db,
+    pub limits: LayerLimits,
+    /// The index of the worker within its LayerPool
+    pub worker: usize,
 }
 
 /// A layer provides a specific service within Omniplex/IBL
@@ -111,6 +194,12 @@ pub trait Layer: Clone + Sized + 'static {
         Ok(())
     }
 
+    /// Returns the key used to route the message in a sticky LayerPool, messages with the same key are
+    /// always handled by the same worker. None routes the message round robin
+    fn route_key(_msg: &Self::Message) -> Option<String> {
+        None
+    }
+
     // Pre-provided helpers
 
     /// Create a LayerData object for this layer
@@ -189,32 +278,198 @@ pub trait Layer: Clone + Sized + 'static {
     }
 }
 
+/// Creates the layer data for a new VM
+///
+/// Layer data caches Lua values (userdata, config tables) which belong to the VM they were created in,
+/// so every VM gets fresh layer data instead of a clone of the previous VM's
+pub type LayerDataFactory<L> = Rc<dyn Fn() -> <L as Layer>::LayerData>;
+
+/// The VM of a layer along with the layer data bound to it
+///
+/// The VM is rebuilt on the next dispatch after it breaks (at most MAX_VM_RESTARTS_PER_WINDOW times per
+/// VM_RESTART_WINDOW) or, in dev mode (luau_dev_dir set), after the layer's Luau code changes on disk.
+/// In-flight dispatches keep running on the old VM until they finish
+#[derive(Clone)]
+pub struct LayerRuntime<L: Layer> {
+    current: Rc<RefCell<(Rc<Vm>, LayerData<L>, u64)>>,
+    data: LayerDataFactory<L>,
+    opts: RuntimeCreateOpts,
+    /// The index of the worker within its LayerPool, used for health reporting
+    worker: usize,
+    watched: Rc<Vec<String>>,
+    /// Held while the VM is being rebuilt so that concurrent dispatches wait for one rebuild instead of
+    /// each starting their own
+    rebuild_lock: Rc<tokio::sync::Mutex<()>>,
+    /// When the VM was restarted after breaking, within the last VM_RESTART_WINDOW
+    restarts: Rc<RefCell<VecDeque<Instant>>>,
+}
+
+#[allow(dead_code)]
+impl<L: Layer> LayerRuntime<L> {
+    /// Creates the VM for a layer whose Luau code lives at entrypoint
+    ///
+    /// data is called to create the layer data of every VM the runtime builds
+    pub async fn new(
+        opts: RuntimeCreateOpts,
+        worker: usize,
+        data: impl Fn() -> L::LayerData + 'static,
+        entrypoint: &str,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let data: LayerDataFactory<L> = Rc::new(data);
+        let watched = watched_entries(entrypoint);
+        let generation = luau_generation(&watched);
+        let (vm, layer_data) = Self::build(opts, worker, data()).await?;
+
+        Ok(Self {
+            current: Rc::new(RefCell::new((Rc::new(vm), layer_data, generation))),
+            data,
+            opts,
+            worker,
+            watched: Rc::new(watched),
+            rebuild_lock: Rc::new(tokio::sync::Mutex::new(())),
+            restarts: Rc::new(RefCell::new(VecDeque::new())),
+        })
+    }
+
+    async fn build(
+        opts: RuntimeCreateOpts,
+        worker: usize,
+        data: L::LayerData,
+    ) -> Result<(Vm, LayerData<L>), Box<dyn std::error::Error + Send + Sync>> {
+        let on_broken: OnBrokenFunc = Box::new(move || {
+            log::error!("VM for layer {} (worker {worker}) has broken, it will be recreated on the next dispatch", L::name());
+            health::record_crash(L::name(), worker);
+        });
+
+        let vm = match luau_source() {
+            LuauSource::Embedded => L::setup_vm(opts, get_luau_vfs(), Some(on_broken)).await?,
+            LuauSource::Directory(dir) => {
+                L::setup_vm(opts, mluau_require::vfs::PhysicalFS::new(dir), Some(on_broken)).await?
+            }
+        };
+
+        let layer_data = L::create_layer_data(data, &vm)
+            .map_err(|e| format!("Failed to create layer data: {e}"))?;
+
+        health::record_running(L::name(), worker);
+        Ok((vm, layer_data))
+    }
+
+    /// Returns the current VM and layer data if the VM is usable and up to date, otherwise whether the VM is broken
+    fn current_if_fresh(&self, generation: u64) -> Result<(Rc<Vm>, LayerData<L>), bool> {
+        let (vm, layer_data, current_generation) = &*self.current.borrow();
+        let broken = vm.is_broken();
+        if !broken && *current_generation == generation {
+            return Ok((vm.clone(), layer_data.clone()));
+        }
+        Err(broken)
+    }
+
+    /// Returns the current VM and layer data, recreating them first if the VM has broken or the layer's
+    /// Luau code has changed
+    ///
+    /// If recreating fails, the error is returned and it is retried on the next call
+    pub async fn current(&self) -> Result<(Rc<Vm>, LayerData<L>), Box<dyn std::error::Error + Send + Sync>> {
+        let generation = luau_generation(&self.watched);
+
+        if let Ok(current) = self.current_if_fresh(generation) {
+            return Ok(current);
+        }
+
+        // Only one dispatch rebuilds the VM, the others wait here and then pick up the rebuilt VM
+        let _rebuild_guard = self.rebuild_lock.lock().await;
+
+        let broken = match self.current_if_fresh(generation) {
+            Ok(current) => return Ok(current),
+            Err(broken) => broken,
+        };
+
+        if broken {
+            self.check_restart_allowed()?;
+            log::info!("Recreating broken VM for layer {} (worker {})", L::name(), self.worker);
+        } else {
+            log::info!("Luau code for layer {} changed, reloading VM (worker {})", L::name(), self.worker);
+        }
+
+        let (vm, layer_data) = Self::build(self.opts, self.worker, (self.data)()).await?;
+        let vm = Rc::new(vm);
+
+        if broken {
+            health::record_restart(L::name(), self.worker);
+        }
+
+        *self.current.borrow_mut() = (vm.clone(), layer_data.clone(), generation);
+        Ok((vm, layer_data))
+    }
+
+    /// Counts a restart of a broken VM, erroring if the VM has already been restarted too often recently
+    fn check_restart_allowed(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let now = Instant::now();
+        let mut restarts = self.restarts.borrow_mut();
+        while restarts.front().is_some_and(|t| now.duration_since(*t) >= VM_RESTART_WINDOW) {
+            restarts.pop_front();
+        }
+
+        if let Some(oldest) = restarts.front().filter(|_| restarts.len() >= MAX_VM_RESTARTS_PER_WINDOW) {
+            let retry_in = VM_RESTART_WINDOW.saturating_sub(now.duration_since(*oldest));
+            health::record_restart_blocked(
+                L::name(),
+                self.worker,
+                chrono::Utc::now() + chrono::Duration::from_std(retry_in).unwrap_or_default(),
+            );
+
+            return Err(format!(
+                "VM for layer {} broke {} times in the last {}s, not restarting it for another {}s",
+                L::name(),
+                restarts.len(),
+                VM_RESTART_WINDOW.as_secs(),
+                retry_in.as_secs() + 1,
+            ).into());
+        }
+
+        restarts.push_back(now);
+        Ok(())
+    }
+}
+
 /// A LayerThread provides a dedicated thread for a specific IBL apoptosis layer
 #[allow(dead_code)]
 #[derive(Clone)]
 pub struct LayerThread<L: Layer> {
-    tx: UnboundedSender<(L::Message, OneshotSender<DispatchLayerResult>)>,
+    tx: Sender<(L::Message, OneshotSender<DispatchLayerResult>)>,
+    /// How many messages the layer is currently handling
+    in_flight: Arc<AtomicUsize>,
     cancellation_token: CancellationToken,
+    /// Cancelled once the layer thread has exited (after cleanup, or if the thread panicked)
+    stopped_token: CancellationToken,
 }
 
 #[allow(dead_code)]
 impl<L: Layer> LayerThread<L> {
     /// Creates a new VmThread
     pub fn new(opts: NewLayerOpts<L>) -> Self {
-        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let (tx, rx) = tokio::sync::mpsc::channel(opts.limits.queue_capacity.max(1));
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let in_flight_ref = in_flight.clone();
         let cancellation_token = CancellationToken::new();
+        let stopped_token = CancellationToken::new();
         let ct_clone = cancellation_token.clone();
+        let stopped_guard = stopped_token.clone().drop_guard();
 
         std::thread::Builder::new()
             .name(format!("LayerThread-{}", std::any::type_name::<L>()))
             .spawn(move || {
-                Self::thread(opts, ct_clone, rx);
+                // Dropped when the thread exits, even on panic
+                let _stopped_guard = stopped_guard;
+                Self::thread(opts, ct_clone, rx, in_flight_ref);
             })
             .expect("Failed to spawn VM thread");
 
         Self {
             tx,
+            in_flight,
             cancellation_token,
+            stopped_token,
         }
     }
 
@@ -222,8 +477,11 @@ impl<L: Layer> LayerThread<L> {
     fn thread(
         opts: NewLayerOpts<L>,
         cancellation_token: CancellationToken,
-        mut rx: UnboundedReceiver<(L::Message, OneshotSender<DispatchLayerResult>)>,
+        mut rx: Receiver<(L::Message, OneshotSender<DispatchLayerResult>)>,
+        in_flight: Arc<AtomicUsize>,
     ) {
+        let dispatch_timeout = opts.limits.dispatch_timeout();
+
         let rt = tokio::runtime::Builder::new_current_thread()
             .enable_all()
             .build_local(LocalOptions::default())
@@ -234,11 +492,23 @@ impl<L: Layer> LayerThread<L> {
 
             loop {
                 select! {
-                    Some(msg) = rx.recv() => {
+                    Some((msg, tx)) = rx.recv() => {
                         let layer_ref = layer.clone();
+                        let in_flight = in_flight.clone();
+                        in_flight.fetch_add(1, Ordering::Relaxed);
                         spawn_local(async move {
-                            let (msg, tx) = msg;
-                            let result = layer_ref.dispatch(msg).await;
+                            // Dropping the dispatch on timeout closes the Lua thread handling it
+                            let result = match dispatch_timeout {
+                                Some(timeout) => match tokio::time::timeout(timeout, layer_ref.dispatch(msg)).await {
+                                    Ok(result) => result,
+                                    Err(_) => {
+                                        log::warn!("Layer {} timed out handling a message after {timeout:?}", L::name());
+                                        Err(LayerDispatchError::Timeout(timeout).into())
+                                    }
+                                },
+                                None => layer_ref.dispatch(msg).await,
+                            };
+                            in_flight.fetch_sub(1, Ordering::Relaxed);
                             let _ = tx.send(result);
                         });
                     }
@@ -254,6 +524,7 @@ impl<L: Layer> LayerThread<L> {
         });
     }
 
+    /// Dispatches a message to the layer, waiting for room if the layer's queue is full
     pub async fn dispatch(&self, msg: L::Message) -> DispatchLayerResult {
         let (tx, rx): (
             OneshotSender<DispatchLayerResult>,
@@ -262,17 +533,59 @@ impl<L: Layer> LayerThread<L> {
 
         self.tx
             .send((msg, tx))
-            .map_err(|e| format!("Failed to send message to layer thread: {e}"))?;
+            .await
+            .map_err(|_| LayerDispatchError::Stopped)?;
 
+        Self::wait_response(rx).await
+    }
+
+    /// Dispatches a message to the layer, failing with LayerDispatchError::QueueFull if the layer's queue is full
+    pub async fn try_dispatch(&self, msg: L::Message) -> DispatchLayerResult {
+        let (tx, rx): (
+            OneshotSender<DispatchLayerResult>,
+            OneshotReceiver<DispatchLayerResult>,
+        ) = channel();
+
+        self.tx
+            .try_send((msg, tx))
+            .map_err(|e| match e {
+                TrySendError::Full(_) => LayerDispatchError::QueueFull,
+                TrySendError::Closed(_) => LayerDispatchError::Stopped,
+            })?;
+
+        Self::wait_response(rx).await
+    }
+
+    async fn wait_response(rx: OneshotReceiver<DispatchLayerResult>) -> DispatchLayerResult {
         match rx.await {
             Ok(result) => result,
             Err(e) => Err(format!("Failed to receive response from layer thread: {e}").into()),
         }
     }
 
-    fn cancel(&self) {
+    /// Returns a snapshot of the layer's queue
+    pub fn queue_stats(&self) -> LayerQueueStats {
+        LayerQueueStats {
+            queued: self.tx.max_capacity() - self.tx.capacity(),
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            capacity: self.tx.max_capacity(),
+        }
+    }
+
+    /// Asks the layer to stop. The layer's cleanup runs on its own thread, use stopped to wait for it
+    pub fn cancel(&self) {
         self.cancellation_token.cancel();
     }
+
+    /// Waits until the layer thread has exited
+    pub async fn stopped(&self) {
+        self.stopped_token.cancelled().await
+    }
+
+    /// Returns whether or not the layer thread has exited
+    pub fn is_stopped(&self) -> bool {
+        self.stopped_token.is_cancelled()
+    }
 }
 
 impl<L: Layer> LuaUserData for LayerThread<L> {
@@ -293,6 +606,27 @@ impl<L: Layer> LuaUserData for LayerThread<L> {
 
             Ok(lua_result)
         });
+
+        methods.add_scheduler_async_method("TryDispatch", |lua, this, msg: LuaValue| async move {
+            let msg: L::Message = lua
+                .from_value(msg)
+                .map_err(|e| LuaError::external(format!("Failed to deserialize message: {e}")))?;
+
+            let result = this
+                .try_dispatch(msg)
+                .await
+                .map_err(|e| LuaError::external(format!("Layer dispatch error: {e}")))?;
+
+            let lua_result = lua
+                .to_value(&result)
+                .map_err(|e| LuaError::external(format!("Failed to serialize result: {e}")))?;
+
+            Ok(lua_result)
+        });
+
+        methods.add_method("QueueStats", |lua, this, ()| {
+            lua.to_value(&this.queue_stats())
+        });
     }
 }
 
@@ -43,6 +43,19 @@ pub struct RuntimeCreateOpts {
     //pub time_slice: Option<std::time::Duration>,
 }
 
+/// Closes a Lua thread when dropped, unless disarmed by taking the thread out
+struct CloseThreadOnDrop(Option<mluau::Thread>);
+
+impl Drop for CloseThreadOnDrop {
+    fn drop(&mut self) {
+        if let Some(th) = self.0.take() {
+            if let Err(e) = th.close() {
+                log::error!("Failed to close cancelled Lua thread: {e}");
+            }
+        }
+    }
+}
+
 pub struct SchedulerHook {
     execution_stop_time: Rc<Cell<Option<std::time::Instant>>>,
     give_time: std::time::Duration,
@@ -416,10 +429,13 @@ impl Vm {
         // Update last_execution_time
         self.update_last_execution_time(std::time::Instant::now());
 
-        let res = self.handle_error(self
-            .scheduler
-            .spawn_thread_and_wait(th, args)
-            .await)?;
+        // If this future is dropped before the thread finishes (e.g. on a dispatch timeout), close the thread
+        // so it stops running in the scheduler
+        let mut close_guard = CloseThreadOnDrop(Some(th.clone()));
+        let res = self.scheduler.spawn_thread_and_wait(th, args).await;
+        close_guard.0 = None;
+
+        let res = self.handle_error(res)?;
 
         {
             let Some(ref lua) = *self.lua.borrow() else {
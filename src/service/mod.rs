@@ -10,4 +10,9 @@ pub mod luacore;  // vendored from khronos
 pub mod bot;
 pub mod lua;
 pub mod session;
+pub mod reminders;
+pub mod supervisor;
+pub mod health;
+pub mod pool;
+pub mod cron;
 pub mod axum;
\ No newline at end of file
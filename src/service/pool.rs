@@ -0,0 +1,358 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tokio::select;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_util::sync::CancellationToken;
+
+use crate::entity::events::{VoteCast, subscribe_votes};
+use crate::service::cron::{CRON_RETRY_DELAY, CronJob, CronRunOutcome, fetch_last_run, run_once};
+use crate::service::layer::{DispatchLayerResult, Layer, LayerQueueStats, LayerThread, NewLayerOpts};
+use crate::service::reminders::{REMINDER_BATCH_SIZE, ReminderManager};
+use crate::types::votes::VoteReminder;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+/// How a LayerPool picks the worker for a message
+pub enum PoolRouting {
+    /// Messages go to each worker in turn
+    #[default]
+    RoundRobin,
+    /// Messages with the same Layer::route_key always go to the same worker, preserving their order.
+    /// Messages without a key go round robin
+    Sticky,
+}
+
+#[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+/// How many workers a layer runs and how messages are routed between them
+pub struct LayerPoolConfig {
+    /// The number of LayerThreads to run, each with its own VM and SharedLayer
+    pub workers: usize,
+    pub routing: PoolRouting,
+}
+
+impl Default for LayerPoolConfig {
+    fn default() -> Self {
+        Self {
+            workers: 1,
+            routing: PoolRouting::RoundRobin,
+        }
+    }
+}
+
+/// Returns the worker a message with the given key is routed to in sticky mode
+fn sticky_worker(key: &str, workers: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    (hasher.finish() % workers as u64) as usize
+}
+
+/// A set of LayerThread workers for one layer behind a single dispatcher
+#[allow(dead_code)]
+#[derive(Clone)]
+pub struct LayerPool<L: Layer> {
+    workers: Arc<Vec<LayerThread<L>>>,
+    routing: PoolRouting,
+    next: Arc<AtomicUsize>,
+    cancellation_token: CancellationToken,
+}
+
+#[allow(dead_code)]
+impl<L: Layer> LayerPool<L> {
+    /// Starts config.workers workers (at least one) for the layer
+    pub fn new(opts: NewLayerOpts<L>, config: LayerPoolConfig) -> Self
+    where
+        L::Config: Clone,
+    {
+        let workers = (0..config.workers.max(1))
+            .map(|worker| {
+                L::load(NewLayerOpts {
+                    config: opts.config.clone(),
+                    pool: opts.pool.clone(),
+                    diesel: opts.diesel.clone(),
+                    limits: opts.limits,
+                    worker,
+                })
+            })
+            .collect();
+
+        Self {
+            workers: Arc::new(workers),
+            routing: config.routing,
+            next: Arc::new(AtomicUsize::new(0)),
+            cancellation_token: CancellationToken::new(),
+        }
+    }
+
+    /// Returns the workers of the pool
+    pub fn workers(&self) -> &[LayerThread<L>] {
+        &self.workers
+    }
+
+    /// Returns the worker that should handle the message
+    pub fn worker_for(&self, msg: &L::Message) -> &LayerThread<L> {
+        if self.routing == PoolRouting::Sticky {
+            if let Some(key) = L::route_key(msg) {
+                return &self.workers[sticky_worker(&key, self.workers.len())];
+            }
+        }
+
+        let i = self.next.fetch_add(1, Ordering::Relaxed) % self.workers.len();
+        &self.workers[i]
+    }
+
+    /// Dispatches a message to a worker, waiting for room if its queue is full
+    pub async fn dispatch(&self, msg: L::Message) -> DispatchLayerResult {
+        self.worker_for(&msg).dispatch(msg).await
+    }
+
+    /// Dispatches a message to a worker, failing with LayerDispatchError::QueueFull if its queue is full
+    pub async fn try_dispatch(&self, msg: L::Message) -> DispatchLayerResult {
+        self.worker_for(&msg).try_dispatch(msg).await
+    }
+
+    /// Dispatches the layer's default message to every worker as a startup event, returning the first error
+    pub async fn dispatch_startup(&self) -> Result<(), crate::Error> {
+        let results = futures::future::join_all(self.workers.iter().map(|w| w.dispatch(L::Message::default()))).await;
+        for result in results {
+            result?;
+        }
+
+        Ok(())
+    }
+
+    /// Forwards every vote event published from now on to the layer as a message
+    ///
+    /// Events are dispatched one at a time in the order they were published. The subscription
+    /// ends when the pool is cancelled
+    pub fn subscribe_vote_events(&self) -> tokio::task::JoinHandle<()>
+    where
+        L::Message: From<VoteCast>,
+    {
+        let mut rx = subscribe_votes();
+        let this = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let event = select! {
+                    event = rx.recv() => event,
+                    _ = this.cancellation_token.cancelled() => return,
+                };
+
+                match event {
+                    Ok(event) => {
+                        if let Err(e) = this.dispatch(L::Message::from(event)).await {
+                            log::error!("Failed to dispatch vote event to layer {}: {e}", L::name());
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        log::warn!("Layer {} fell behind and missed {skipped} vote events", L::name());
+                    }
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        })
+    }
+
+    /// Polls for vote reminders that have come due and dispatches each one to the layer as a message
+    ///
+    /// Reminders are persisted so ones that come due while nothing is polling fire on the next poll. A reminder
+    /// that fails to be dispatched is retried later. Polling stops when the pool is cancelled
+    pub fn schedule_vote_reminders(&self, reminders: ReminderManager, poll_interval: std::time::Duration) -> tokio::task::JoinHandle<()>
+    where
+        L::Message: From<VoteReminder>,
+    {
+        let this = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(poll_interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+            loop {
+                select! {
+                    _ = interval.tick() => {},
+                    _ = this.cancellation_token.cancelled() => return,
+                }
+
+                let due = match reminders.claim_due(REMINDER_BATCH_SIZE).await {
+                    Ok(due) => due,
+                    Err(e) => {
+                        log::error!("Failed to fetch due vote reminders for layer {}: {e}", L::name());
+                        continue;
+                    }
+                };
+
+                for reminder in due {
+                    if let Err(e) = this.dispatch(L::Message::from(reminder.clone())).await {
+                        log::error!("Failed to dispatch vote reminder to layer {}: {e}", L::name());
+
+                        if let Err(e) = reminders.retry_later(&reminder).await {
+                            log::error!("Failed to reschedule vote reminder for {} on {} {}: {e}", reminder.user_id, reminder.target_type, reminder.target_id);
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    /// Dispatches each job's message to the layer on the job's cron schedule, one task per job
+    ///
+    /// Jobs are locked while they run and their last run is stored in layer_cron_runs (see run_once), so each
+    /// scheduled run succeeds on only one instance. Failed runs, and runs whose instance stopped mid-run, are retried
+    /// every CRON_RETRY_DELAY until the next run of the job is due. A run missed while no instance was up happens
+    /// once on startup. Jobs stop when the pool is cancelled
+    pub fn schedule_cron(&self, jobs: Vec<CronJob>, pool: sqlx::PgPool) -> Result<Vec<tokio::task::JoinHandle<()>>, crate::Error> {
+        // Catch bad messages at startup rather than on the first run
+        for job in jobs.iter() {
+            serde_json::from_value::<L::Message>(job.message.clone())
+                .map_err(|e| format!("Invalid message for cron job {} of layer {}: {e}", job.name, L::name()))?;
+        }
+
+        let handles = jobs
+            .into_iter()
+            .map(|job| {
+                let this = self.clone();
+                let pool = pool.clone();
+
+                tokio::spawn(async move {
+                    let mut after = match fetch_last_run(&pool, L::name(), &job.name).await {
+                        Ok(last_run) => last_run.map_or_else(chrono::Utc::now, |last_run| last_run.resume_after()),
+                        Err(e) => {
+                            log::error!("Failed to fetch last run of cron job {} of layer {}: {e}", job.name, L::name());
+                            chrono::Utc::now()
+                        }
+                    };
+
+                    loop {
+                        let Some(fire_at) = job.next_after(after) else {
+                            log::warn!("Cron job {} of layer {} has no more scheduled runs", job.name, L::name());
+                            return;
+                        };
+
+                        let wait = (fire_at - chrono::Utc::now()).to_std().unwrap_or_default();
+                        select! {
+                            _ = tokio::time::sleep(wait) => {},
+                            _ = this.cancellation_token.cancelled() => return,
+                        }
+
+                        loop {
+                            let res = run_once(&pool, L::name(), &job.name, fire_at, async || {
+                                let msg: L::Message = serde_json::from_value(job.message.clone())?;
+                                this.dispatch(msg).await?;
+                                Ok(())
+                            })
+                            .await;
+
+                            match res {
+                                Ok(CronRunOutcome::Ran | CronRunOutcome::AlreadyRan) => break,
+                                // Checked again later in case the other instance stops or fails
+                                Ok(CronRunOutcome::Busy) => {}
+                                Err(e) => log::error!("Cron job {} of layer {} failed: {e}", job.name, L::name()),
+                            }
+
+                            let retry_at = chrono::Utc::now() + CRON_RETRY_DELAY;
+                            if job.next_after(fire_at).is_none_or(|next| next <= retry_at) {
+                                break;
+                            }
+
+                            select! {
+                                _ = tokio::time::sleep(CRON_RETRY_DELAY) => {},
+                                _ = this.cancellation_token.cancelled() => return,
+                            }
+                        }
+
+                        // Runs missed while this one was running are skipped
+                        after = fire_at.max(chrono::Utc::now());
+                    }
+                })
+            })
+            .collect();
+
+        Ok(handles)
+    }
+
+    /// Asks every worker to stop
+    pub fn cancel(&self) {
+        self.cancellation_token.cancel();
+        for worker in self.workers.iter() {
+            worker.cancel();
+        }
+    }
+
+    /// Waits until every worker thread has exited
+    pub async fn stopped(&self) {
+        futures::future::join_all(self.workers.iter().map(|w| w.stopped())).await;
+    }
+
+    /// Returns whether or not every worker thread has exited
+    pub fn is_stopped(&self) -> bool {
+        self.workers.iter().all(|w| w.is_stopped())
+    }
+
+    /// Returns the queue stats of all workers combined
+    pub fn queue_stats(&self) -> LayerQueueStats {
+        self.workers.iter().map(|w| w.queue_stats()).fold(
+            LayerQueueStats { queued: 0, in_flight: 0, capacity: 0 },
+            |acc, s| LayerQueueStats {
+                queued: acc.queued + s.queued,
+                in_flight: acc.in_flight + s.in_flight,
+                capacity: acc.capacity + s.capacity,
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::sample::SampleLayerEvent;
+    use crate::layers::sample::samplelayer::SampleLayer;
+
+    #[test]
+    fn test_sticky_worker() {
+        for workers in 1..8 {
+            let worker = sticky_worker("bot:1234", workers);
+            assert!(worker < workers);
+            assert_eq!(worker, sticky_worker("bot:1234", workers));
+        }
+    }
+
+    #[test]
+    fn test_sample_layer_route_key() {
+        let vote = |user_id: &str| {
+            SampleLayerEvent::VoteCast(VoteCast {
+                target_id: "1234".to_string(),
+                target_type: "bot".to_string(),
+                user_id: user_id.to_string(),
+                votes: 1,
+                upvote: true,
+                created_at: chrono::Utc::now(),
+            })
+        };
+        let reminder = || {
+            SampleLayerEvent::VoteReminder(VoteReminder {
+                user_id: "2".to_string(),
+                target_id: "1234".to_string(),
+                target_type: "bot".to_string(),
+                due_at: None,
+                last_fired_at: None,
+                created_at: chrono::Utc::now(),
+            })
+        };
+
+        let key = SampleLayer::route_key(&vote("1")).unwrap();
+        assert_eq!(key, "bot:1234");
+
+        // Every vote event of an entity reaches the same worker, whoever voted
+        for workers in 1..8 {
+            let worker = sticky_worker(&key, workers);
+            for event in [vote("1"), vote("2"), reminder()] {
+                assert_eq!(sticky_worker(&SampleLayer::route_key(&event).unwrap(), workers), worker);
+            }
+        }
+
+        assert_eq!(SampleLayer::route_key(&SampleLayerEvent::TestEvent { data: "test".to_string() }), None);
+    }
+}
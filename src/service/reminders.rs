@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use mlua_scheduler::LuaSchedulerAsyncUserData;
+use mluau::prelude::*;
+
+use crate::{service::sharedlayer::SharedLayerDb, types::votes::VoteReminder};
+
+/// How often the scheduler checks for due reminders by default
+pub const DEFAULT_REMINDER_POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// The maximum amount of reminders claimed per poll
+pub const REMINDER_BATCH_SIZE: i64 = 100;
+/// How long to wait before retrying a reminder that failed to be delivered
+pub const REMINDER_RETRY_DELAY: chrono::Duration = chrono::Duration::minutes(5);
+
+/// ReminderManager provides methods to manage vote reminders across all entity types
+#[derive(Clone)]
+pub struct ReminderManager {
+    shared_db: SharedLayerDb,
+}
+
+#[allow(dead_code)]
+impl ReminderManager {
+    /// Creates a new ReminderManager
+    pub(super) fn new(shared_db: SharedLayerDb) -> Self {
+        Self { shared_db }
+    }
+
+    /// Opts a user in to vote reminders for an entity
+    pub async fn opt_in(&self, target_type: &str, target_id: &str, user_id: &str) -> Result<VoteReminder, crate::Error> {
+        let Some(manager) = self.shared_db.entity_manager_for(target_type) else {
+            return Err(format!("Unknown target type: {target_type}").into());
+        };
+
+        manager.enable_vote_reminder(target_id, user_id).await
+    }
+
+    /// Opts a user out of vote reminders for an entity, returning whether or not the user was opted in
+    pub async fn opt_out(&self, target_type: &str, target_id: &str, user_id: &str) -> Result<bool, crate::Error> {
+        let Some(manager) = self.shared_db.entity_manager_for(target_type) else {
+            return Err(format!("Unknown target type: {target_type}").into());
+        };
+
+        manager.disable_vote_reminder(target_id, user_id).await
+    }
+
+    /// Returns all vote reminders a user has opted in to
+    pub async fn list_for_user(&self, user_id: &str) -> Result<Vec<VoteReminder>, crate::Error> {
+        let reminders: Vec<VoteReminder> = sqlx::query_as(
+            "SELECT user_id, target_id, target_type, due_at, last_fired_at, created_at FROM entity_vote_reminders
+            WHERE user_id = $1 ORDER BY created_at ASC",
+        )
+        .bind(user_id)
+        .fetch_all(self.shared_db.pool())
+        .await?;
+
+        Ok(reminders)
+    }
+
+    /// Claims up to limit reminders that have come due, clearing their due time so they only fire once
+    ///
+    /// Rows locked by another scheduler are skipped so multiple processes can poll at the same time
+    pub async fn claim_due(&self, limit: i64) -> Result<Vec<VoteReminder>, crate::Error> {
+        let reminders: Vec<VoteReminder> = sqlx::query_as(
+            "UPDATE entity_vote_reminders r SET due_at = NULL, last_fired_at = NOW()
+            FROM (
+                SELECT user_id, target_id, target_type, due_at FROM entity_vote_reminders
+                WHERE due_at <= NOW() ORDER BY due_at ASC LIMIT $1 FOR UPDATE SKIP LOCKED
+            ) due
+            WHERE r.user_id = due.user_id AND r.target_id = due.target_id AND r.target_type = due.target_type
+            RETURNING r.user_id, r.target_id, r.target_type, due.due_at, r.last_fired_at, r.created_at",
+        )
+        .bind(limit)
+        .fetch_all(self.shared_db.pool())
+        .await?;
+
+        Ok(reminders)
+    }
+
+    /// Makes a claimed reminder due again after REMINDER_RETRY_DELAY, unless the user has voted since
+    pub async fn retry_later(&self, reminder: &VoteReminder) -> Result<(), crate::Error> {
+        sqlx::query(
+            "UPDATE entity_vote_reminders SET due_at = $1
+            WHERE user_id = $2 AND target_id = $3 AND target_type = $4 AND due_at IS NULL",
+        )
+        .bind(chrono::Utc::now() + REMINDER_RETRY_DELAY)
+        .bind(&reminder.user_id)
+        .bind(&reminder.target_id)
+        .bind(&reminder.target_type)
+        .execute(self.shared_db.pool())
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl LuaUserData for ReminderManager {
+    fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
+        methods.add_scheduler_async_method("OptIn", async |lua, this, (target_type, target_id, user_id): (String, String, String)| {
+            let reminder = this
+                .opt_in(&target_type, &target_id, &user_id)
+                .await
+                .map_err(|e| LuaError::external(e.to_string()))?;
+            lua.to_value(&reminder)
+        });
+
+        methods.add_scheduler_async_method("OptOut", async |_lua, this, (target_type, target_id, user_id): (String, String, String)| {
+            this.opt_out(&target_type, &target_id, &user_id)
+                .await
+                .map_err(|e| LuaError::external(e.to_string()))
+        });
+
+        methods.add_scheduler_async_method("ListForUser", async |lua, this, user_id: String| {
+            let reminders = this
+                .list_for_user(&user_id)
+                .await
+                .map_err(|e| LuaError::external(e.to_string()))?;
+            lua.to_value(&reminders)
+        });
+    }
+}
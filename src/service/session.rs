@@ -124,7 +124,7 @@ impl SessionManager {
             return Ok(SessionPermit::EntityNotSupported);
         };
 
-        let flags = manager.entity().flags(&auth.target_id).await?;
+        let flags = manager.flags(&auth.target_id).await?;
 
         if flags.contains(EntityFlags::BANNED) {
             return Ok(SessionPermit::ApiBanned {
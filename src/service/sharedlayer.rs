@@ -1,7 +1,13 @@
 use crate::Db;
-use crate::entity::EntityType;
-use crate::entity::manager::EntityManager;
+use crate::entity::{Entity, EntityEnumSummaryObject, EntityFlags, EntityType, EntityVoteInfo};
+use crate::entity::cache::ENTITY_CACHE;
+use crate::entity::flags::{ChangeFlagsError, FlagAction, flag_names, parse_flag_names};
+use crate::entity::multipliers::{SET_VOTE_RULES_PERM, VoteRules, VoteRulesError, reset_vote_rules, set_vote_rules, vote_rules_for};
+use crate::entity::lua::{LuaEntityManager, RESET_VOTES_PERM};
+use crate::entity::manager::{DEFAULT_RECONCILE_BATCH_SIZE, DEFAULT_RESET_BATCH_SIZE, ENTITY_VOTE_COLS, EntityManager, MAX_LEADERBOARD_LIMIT};
+use crate::service::reminders::ReminderManager;
 use crate::service::session::SessionManager;
+use crate::types::votes::{EntityVote, LeaderboardEntry, UserVoteHistoryEntry, UserVoteHistoryFilter, VoteCountChange, VoteReset};
 
 use super::cacheserver::CacheServerManager;
 use super::kittycat as srv_kittycat;
@@ -10,19 +16,23 @@ use mlua_scheduler::LuaSchedulerAsyncUserData;
 use mluau::prelude::*;
 use sqlx::Row;
 use sqlx::types::Uuid;
+use std::collections::HashMap;
 use std::rc::Rc;
 
+/// The maximum amount of votes that can be fetched from a user's vote history at once
+pub const MAX_VOTE_HISTORY_LIMIT: i64 = 100;
+
 /// Internally needed so other parts of SharedLayer can access the database
 /// and entity manager creation related methods
 #[derive(Clone)]
-pub(super) struct SharedLayerDb {
+pub(crate) struct SharedLayerDb {
     pool: sqlx::PgPool,
     diesel: Db,
 }
 
 #[allow(dead_code)]
 impl SharedLayerDb {
-    fn new(pool: sqlx::PgPool, diesel: Db) -> Self {
+    pub(crate) fn new(pool: sqlx::PgPool, diesel: Db) -> Self {
         Self { pool, diesel }
     }
 
@@ -102,6 +112,70 @@ impl SharedLayerDb {
         Ok(sp)
     }
 
+    /// Returns whether or not a user has the given kittycat staff permission
+    pub async fn has_staff_perm(&self, userid: &str, perm: &str) -> Result<bool, crate::Error> {
+        let perms = self.get_user_staff_perms(userid.to_string()).await?;
+        Ok(kittycat::perms::has_perm(
+            &perms.resolve(),
+            &kittycat::perms::Permission::from_string(perm),
+        ))
+    }
+
+    /// Sets or clears flags on an entity on behalf of a staff member, checking their kittycat permissions first
+    pub async fn change_entity_flags(
+        &self,
+        target_type: &str,
+        target_id: &str,
+        action: FlagAction,
+        flags: EntityFlags,
+        actor_id: &str,
+        reason: &str,
+    ) -> Result<EntityFlags, crate::Error> {
+        if !self.has_staff_perm(actor_id, action.perm()).await? {
+            return Err(ChangeFlagsError::MissingPerm(action).into());
+        }
+
+        let Some(manager) = self.entity_manager_for(target_type) else {
+            return Err(ChangeFlagsError::UnknownTargetType(target_type.to_string()).into());
+        };
+
+        match action {
+            FlagAction::Set => manager.set_flags(target_id, flags, actor_id, reason).await,
+            FlagAction::Clear => manager.clear_flags(target_id, flags, actor_id, reason).await,
+        }
+    }
+
+    /// Replaces the vote rules of a target type on behalf of a staff member, checking their kittycat permissions first
+    ///
+    /// Returns the new rules
+    pub async fn set_vote_rules(&self, target_type: &str, rules: VoteRules, actor_id: &str) -> Result<VoteRules, crate::Error> {
+        self.check_vote_rules_change(target_type, actor_id).await?;
+        set_vote_rules(&self.pool, target_type, &rules, actor_id).await?;
+        Ok(rules)
+    }
+
+    /// Reverts the vote rules of a target type to the ones in the config on behalf of a staff member,
+    /// checking their kittycat permissions first
+    ///
+    /// Returns the rules now in effect
+    pub async fn reset_vote_rules(&self, target_type: &str, actor_id: &str) -> Result<VoteRules, crate::Error> {
+        self.check_vote_rules_change(target_type, actor_id).await?;
+        reset_vote_rules(&self.pool, target_type).await?;
+        vote_rules_for(&self.pool, target_type).await
+    }
+
+    async fn check_vote_rules_change(&self, target_type: &str, actor_id: &str) -> Result<(), crate::Error> {
+        if !self.has_staff_perm(actor_id, SET_VOTE_RULES_PERM).await? {
+            return Err(VoteRulesError::MissingPerm.into());
+        }
+
+        if self.entity_manager_for(target_type).is_none() {
+            return Err(VoteRulesError::UnknownTargetType(target_type.to_string()).into());
+        }
+
+        Ok(())
+    }
+
     /// Creates a new EntityManager for the given entity type
     pub fn entity_manager_for(&self, target_type: &str) -> Option<crate::entity::AnyEntityManager> {
         let Some(manager) = EntityType::from_name(target_type, self.pool.clone(), self.diesel.clone()) else {
@@ -109,6 +183,133 @@ impl SharedLayerDb {
         };
         Some(EntityManager::new(manager))
     }
+
+    /// Returns the entities with the most votes across the given target types (or all target types if None)
+    pub async fn leaderboard(
+        &self,
+        target_types: Option<Vec<String>>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<LeaderboardEntry<EntityEnumSummaryObject>>, crate::Error> {
+        let rows: Vec<(String, String, i32)> = sqlx::query_as(
+            "SELECT target_id, target_type, approximate_votes FROM entity_approx_votes
+            WHERE ($1::TEXT[] IS NULL OR target_type = ANY($1))
+            ORDER BY approximate_votes DESC, target_id ASC LIMIT $2 OFFSET $3",
+        )
+        .bind(target_types)
+        .bind(limit.clamp(0, MAX_LEADERBOARD_LIMIT))
+        .bind(offset.max(0))
+        .fetch_all(&self.pool)
+        .await?;
+
+        let summaries = futures::future::join_all(rows.iter().map(|(target_id, target_type, _)| async move {
+            let entity = EntityType::from_name(target_type, self.pool.clone(), self.diesel.clone())?;
+            match EntityManager::new(entity).get_summary(target_id).await {
+                Ok(summary) => Some(summary),
+                Err(e) => {
+                    log::warn!("Failed to fetch summary for {target_type} {target_id} on leaderboard: {e}");
+                    None
+                }
+            }
+        }))
+        .await;
+
+        let mut entries = Vec::with_capacity(rows.len());
+        for (i, ((target_id, target_type, approximate_votes), summary)) in rows.into_iter().zip(summaries).enumerate() {
+
+            entries.push(LeaderboardEntry {
+                rank: offset.max(0) + i as i64 + 1,
+                target_id,
+                target_type,
+                approximate_votes,
+                summary,
+            });
+        }
+
+        Ok(entries)
+    }
+
+    /// Returns the votes made by a user across all entities, newest first
+    pub async fn fetch_user_votes(
+        &self,
+        user_id: &str,
+        filter: UserVoteHistoryFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<UserVoteHistoryEntry<EntityEnumSummaryObject>>, crate::Error> {
+        let mut qb = sqlx::QueryBuilder::<sqlx::Postgres>::new("SELECT ");
+        qb.push(ENTITY_VOTE_COLS);
+        qb.push(" FROM entity_votes WHERE author = ").push_bind(user_id.to_string());
+
+        if !filter.include_void {
+            qb.push(" AND void = false");
+        }
+        if let Some(target_type) = filter.target_type {
+            qb.push(" AND target_type = ").push_bind(target_type);
+        }
+        if let Some(from) = filter.from {
+            qb.push(" AND created_at >= ").push_bind(from);
+        }
+        if let Some(to) = filter.to {
+            qb.push(" AND created_at < ").push_bind(to);
+        }
+
+        qb.push(" ORDER BY created_at DESC, itag ASC LIMIT ")
+            .push_bind(limit.clamp(0, MAX_VOTE_HISTORY_LIMIT))
+            .push(" OFFSET ")
+            .push_bind(offset.max(0));
+
+        let votes: Vec<EntityVote> = qb.build_query_as().fetch_all(&self.pool).await?;
+
+        // A page usually contains many votes on the same entity, only fetch each summary once
+        let mut summaries: HashMap<(String, String), Option<EntityEnumSummaryObject>> = HashMap::new();
+        let mut entries = Vec::with_capacity(votes.len());
+        for vote in votes {
+            let key = (vote.target_type.clone(), vote.target_id.clone());
+            let summary = match summaries.get(&key) {
+                Some(summary) => summary.clone(),
+                None => {
+                    let summary = match EntityType::from_name(&vote.target_type, self.pool.clone(), self.diesel.clone()) {
+                        Some(entity) => match EntityManager::new(entity).get_summary(&vote.target_id).await {
+                            Ok(summary) => Some(summary),
+                            Err(e) => {
+                                log::warn!("Failed to fetch summary for {} {} in vote history: {e}", vote.target_type, vote.target_id);
+                                None
+                            }
+                        },
+                        None => None,
+                    };
+                    summaries.insert(key, summary.clone());
+                    summary
+                }
+            };
+
+            entries.push(UserVoteHistoryEntry { vote, summary });
+        }
+
+        Ok(entries)
+    }
+
+    /// Reconciles the approximate vote counts of every entity of every known target type
+    pub async fn reconcile_all_votes(&self, batch_size: i64) -> Result<Vec<VoteCountChange>, crate::Error> {
+        let target_types: Vec<String> = sqlx::query_scalar(
+            "SELECT DISTINCT target_type FROM entity_approx_votes UNION SELECT DISTINCT target_type FROM entity_votes",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut changes = Vec::new();
+        for target_type in target_types {
+            let Some(manager) = self.entity_manager_for(&target_type) else {
+                log::warn!("Skipping vote reconciliation for unknown target type {target_type}");
+                continue;
+            };
+
+            changes.extend(manager.reconcile_votes(None, batch_size).await?);
+        }
+
+        Ok(changes)
+    }
 }
 
 /// SharedLayer provides common methods across IBL's entire backend
@@ -120,6 +321,7 @@ pub struct SharedLayer {
     db: SharedLayerDb,
     cache_server_manager: CacheServerManager,
     session_manager: SessionManager,
+    reminder_manager: ReminderManager,
 }
 
 #[allow(dead_code)]
@@ -133,6 +335,7 @@ impl SharedLayer {
         Self {
             cache_server_manager: CacheServerManager::new(pool.clone()),
             session_manager: SessionManager::new(db.clone()),
+            reminder_manager: ReminderManager::new(db.clone()),
             db,
         }
     }
@@ -147,6 +350,11 @@ impl SharedLayer {
         &self.session_manager
     }
 
+    /// Returns the underlying reminder manager
+    pub fn reminder_manager(&self) -> &ReminderManager {
+        &self.reminder_manager
+    }
+
     /// Returns the state of a bot by its user ID on Omni/IBL
     ///
     /// Returns None if the bot is not found
@@ -166,6 +374,91 @@ impl SharedLayer {
     pub fn entity_manager_for(&self, target_type: &str) -> Option<crate::entity::AnyEntityManager> {
         self.db.entity_manager_for(target_type)
     }
+
+    /// Returns whether or not a user has the given kittycat staff permission
+    pub async fn has_staff_perm(&self, userid: &str, perm: &str) -> Result<bool, crate::Error> {
+        self.db.has_staff_perm(userid, perm).await
+    }
+
+    /// Sets or clears flags on an entity on behalf of a staff member, checking their kittycat permissions first
+    pub async fn change_entity_flags(
+        &self,
+        target_type: &str,
+        target_id: &str,
+        action: FlagAction,
+        flags: EntityFlags,
+        actor_id: &str,
+        reason: &str,
+    ) -> Result<EntityFlags, crate::Error> {
+        self.db.change_entity_flags(target_type, target_id, action, flags, actor_id, reason).await
+    }
+
+    /// Returns the vote rules of a target type, see multipliers::vote_rules_for
+    pub async fn vote_rules(&self, target_type: &str) -> Result<VoteRules, crate::Error> {
+        vote_rules_for(self.db.pool(), target_type).await
+    }
+
+    /// Replaces the vote rules of a target type on behalf of a staff member, checking their kittycat permissions first
+    pub async fn set_vote_rules(&self, target_type: &str, rules: VoteRules, actor_id: &str) -> Result<VoteRules, crate::Error> {
+        self.db.set_vote_rules(target_type, rules, actor_id).await
+    }
+
+    /// Reverts the vote rules of a target type to the ones in the config on behalf of a staff member,
+    /// checking their kittycat permissions first
+    pub async fn reset_vote_rules(&self, target_type: &str, actor_id: &str) -> Result<VoteRules, crate::Error> {
+        self.db.reset_vote_rules(target_type, actor_id).await
+    }
+
+    /// Evaluates the vote rules of an entity, taking the perks of the user into account if given
+    pub async fn evaluate_vote_rules(&self, target_type: &str, id: &str, user_id: Option<&str>) -> Result<EntityVoteInfo, crate::Error> {
+        let Some(manager) = self.entity_manager_for(target_type) else {
+            return Err(format!("Unknown target type: {target_type}").into());
+        };
+
+        manager.entity().get_vote_info(id, user_id).await
+    }
+
+    /// Returns the entities with the most votes across the given target types (or all target types if None)
+    pub async fn leaderboard(
+        &self,
+        target_types: Option<Vec<String>>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<LeaderboardEntry<EntityEnumSummaryObject>>, crate::Error> {
+        self.db.leaderboard(target_types, limit, offset).await
+    }
+
+    /// Reconciles the approximate vote counts of every entity of every known target type
+    pub async fn reconcile_all_votes(&self, batch_size: i64) -> Result<Vec<VoteCountChange>, crate::Error> {
+        self.db.reconcile_all_votes(batch_size).await
+    }
+
+    /// Returns the votes made by a user across all entities, newest first
+    pub async fn fetch_user_votes(
+        &self,
+        user_id: &str,
+        filter: UserVoteHistoryFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<UserVoteHistoryEntry<EntityEnumSummaryObject>>, crate::Error> {
+        self.db.fetch_user_votes(user_id, filter, limit, offset).await
+    }
+
+    /// Resets the votes of every entity of the given target type for the given period on behalf of a staff member,
+    /// checking their kittycat permissions first
+    ///
+    /// See EntityManager::reset_votes
+    pub async fn reset_votes(&self, target_type: &str, period: &str, batch_size: i64, actor_id: &str) -> Result<VoteReset, crate::Error> {
+        if !self.has_staff_perm(actor_id, RESET_VOTES_PERM).await? {
+            return Err(format!("You need the {RESET_VOTES_PERM} permission to do this").into());
+        }
+
+        let Some(manager) = self.entity_manager_for(target_type) else {
+            return Err(format!("Unknown target type: {target_type}").into());
+        };
+
+        manager.reset_votes(period, batch_size).await
+    }
 }
 
 impl IntoLua for SharedLayer {
@@ -184,6 +477,7 @@ pub struct LuaSharedLayer {
     // Cache any computed fields here
     cache_server_manager_cache: Rc<OptionalValue<LuaAnyUserData>>,
     session_manager_cache: Rc<OptionalValue<LuaAnyUserData>>,
+    reminder_manager_cache: Rc<OptionalValue<LuaAnyUserData>>,
     shared_layer_ud: Rc<OptionalValue<LuaAnyUserData>>,
 }
 
@@ -193,6 +487,7 @@ impl LuaSharedLayer {
             shared,
             cache_server_manager_cache: Rc::new(OptionalValue::new()),
             session_manager_cache: Rc::new(OptionalValue::new()),
+            reminder_manager_cache: Rc::new(OptionalValue::new()),
             shared_layer_ud: Rc::new(OptionalValue::new()),
         }
     }
@@ -217,6 +512,11 @@ impl LuaUserData for LuaSharedLayer {
             this.session_manager_cache
                 .get_failable(|| lua.create_any_userdata(this.session_manager.clone()))
         });
+
+        fields.add_field_method_get("ReminderManager", |lua, this| {
+            this.reminder_manager_cache
+                .get_failable(|| lua.create_any_userdata(this.reminder_manager.clone()))
+        });
     }
 
     fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
@@ -232,6 +532,76 @@ impl LuaUserData for LuaSharedLayer {
             },
         );
 
+        methods.add_scheduler_async_method(
+            "SetFlags",
+            |_lua, this, (target_type, target_id, flags, actor_id, reason): (String, String, Vec<String>, String, String)| async move {
+                let flags = parse_flag_names(&flags).map_err(|e| LuaError::external(e.to_string()))?;
+                let flags = this
+                    .change_entity_flags(&target_type, &target_id, FlagAction::Set, flags, &actor_id, &reason)
+                    .await
+                    .map_err(|e| LuaError::external(e.to_string()))?;
+                Ok(flag_names(flags))
+            },
+        );
+
+        methods.add_scheduler_async_method(
+            "ClearFlags",
+            |_lua, this, (target_type, target_id, flags, actor_id, reason): (String, String, Vec<String>, String, String)| async move {
+                let flags = parse_flag_names(&flags).map_err(|e| LuaError::external(e.to_string()))?;
+                let flags = this
+                    .change_entity_flags(&target_type, &target_id, FlagAction::Clear, flags, &actor_id, &reason)
+                    .await
+                    .map_err(|e| LuaError::external(e.to_string()))?;
+                Ok(flag_names(flags))
+            },
+        );
+
+        methods.add_scheduler_async_method("GetVoteRules", |lua, this, target_type: String| async move {
+            let rules = this
+                .vote_rules(&target_type)
+                .await
+                .map_err(|e| LuaError::external(e.to_string()))?;
+            lua.to_value(&rules)
+        });
+
+        methods.add_scheduler_async_method(
+            "SetVoteRules",
+            |lua, this, (actor_id, target_type, rules): (String, String, LuaValue)| async move {
+                let rules: VoteRules = lua.from_value(rules)?;
+                let rules = this
+                    .set_vote_rules(&target_type, rules, &actor_id)
+                    .await
+                    .map_err(|e| LuaError::external(e.to_string()))?;
+                lua.to_value(&rules)
+            },
+        );
+
+        methods.add_scheduler_async_method(
+            "ResetVoteRules",
+            |lua, this, (actor_id, target_type): (String, String)| async move {
+                let rules = this
+                    .reset_vote_rules(&target_type, &actor_id)
+                    .await
+                    .map_err(|e| LuaError::external(e.to_string()))?;
+                lua.to_value(&rules)
+            },
+        );
+
+        methods.add_scheduler_async_method(
+            "EvaluateVoteRules",
+            |lua, this, (target_type, target_id, user_id): (String, String, Option<String>)| async move {
+                let vote_info = this
+                    .evaluate_vote_rules(&target_type, &target_id, user_id.as_deref())
+                    .await
+                    .map_err(|e| LuaError::external(e.to_string()))?;
+                lua.to_value(&vote_info)
+            },
+        );
+
+        methods.add_method("EntityCacheStats", |lua, _this, ()| {
+            lua.to_value(&ENTITY_CACHE.stats())
+        });
+
         methods.add_scheduler_async_method("GetBotState", |_lua, this, botid: String| async move {
             let state = this
                 .get_bot_state(botid)
@@ -239,5 +609,53 @@ impl LuaUserData for LuaSharedLayer {
                 .map_err(LuaError::external)?;
             Ok(state)
         });
+
+        methods.add_scheduler_async_method(
+            "Leaderboard",
+            |lua, this, (target_types, limit, offset): (Option<Vec<String>>, i64, Option<i64>)| async move {
+                let entries = this
+                    .leaderboard(target_types, limit, offset.unwrap_or(0))
+                    .await
+                    .map_err(|e| LuaError::external(e.to_string()))?;
+                lua.to_value(&entries)
+            },
+        );
+
+        methods.add_scheduler_async_method(
+            "FetchUserVotes",
+            |lua, this, (user_id, filter, limit, offset): (String, Option<UserVoteHistoryFilter>, i64, Option<i64>)| async move {
+                let entries = this
+                    .fetch_user_votes(&user_id, filter.unwrap_or_default(), limit, offset.unwrap_or(0))
+                    .await
+                    .map_err(|e| LuaError::external(e.to_string()))?;
+                lua.to_value(&entries)
+            },
+        );
+
+        methods.add_scheduler_async_method(
+            "ReconcileAllVotes",
+            |lua, this, batch_size: Option<i64>| async move {
+                let changes = this
+                    .reconcile_all_votes(batch_size.unwrap_or(DEFAULT_RECONCILE_BATCH_SIZE))
+                    .await
+                    .map_err(|e| LuaError::external(e.to_string()))?;
+                lua.to_value(&changes)
+            },
+        );
+
+        methods.add_scheduler_async_method(
+            "ResetVotes",
+            |lua, this, (actor_id, target_type, period, batch_size): (String, String, String, Option<i64>)| async move {
+                let reset = this
+                    .reset_votes(&target_type, &period, batch_size.unwrap_or(DEFAULT_RESET_BATCH_SIZE), &actor_id)
+                    .await
+                    .map_err(|e| LuaError::external(e.to_string()))?;
+                lua.to_value(&reset)
+            },
+        );
+
+        methods.add_method("EntityManagerFor", |_lua, this, target_type: String| {
+            Ok(this.entity_manager_for(&target_type).map(LuaEntityManager::new))
+        });
     }
 }
@@ -0,0 +1,256 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+
+use crate::layers::api::ApiLayer;
+use crate::layers::sample::samplelayer::SampleLayer;
+use crate::service::layer::{Layer, LayerLimits, LayerQueueStats, NewLayerOpts};
+use crate::service::cron::{CronJob, ScheduledDispatch};
+use crate::service::pool::{LayerPool, LayerPoolConfig};
+use crate::service::reminders::DEFAULT_REMINDER_POLL_INTERVAL;
+use crate::service::sharedlayer::SharedLayer;
+
+/// How long layers get to clean up on shutdown by default
+pub const DEFAULT_SHUTDOWN_DEADLINE: Duration = Duration::from_secs(30);
+
+/// Starts a layer from its JSON config
+type StartLayerFn = for<'a> fn(&'a mut LayerSupervisor, serde_json::Value, LayerSettings) -> BoxFuture<'a, Result<(), crate::Error>>;
+
+/// Config keys layers were read from before the supervisor keyed them on Layer::name(), as (name, old key)
+///
+/// These are only used when the config has no entry under the layer's name
+const LEGACY_CONFIG_KEYS: [(&str, &str); 1] = [("samplelayer", "sample")];
+
+/// Returns the config of a layer stored under its legacy key, if any
+fn legacy_config<'a>(name: &str, config: &'a serde_json::Value) -> Option<&'a serde_json::Value> {
+    let (_, key) = LEGACY_CONFIG_KEYS.iter().find(|(layer_name, _)| *layer_name == name)?;
+    let cfg = config.get(*key)?;
+    log::warn!("Reading the config of layer {name} from the deprecated key {key}, rename it to {name}");
+    Some(cfg)
+}
+
+/// Every layer that can be started from config, keyed by Layer::name()
+const KNOWN_LAYERS: [(fn() -> &'static str, StartLayerFn); 2] = [
+    (SampleLayer::name, |sv, cfg, settings| {
+        Box::pin(async move {
+            let th = sv.start::<SampleLayer>(serde_json::from_value(cfg)?, settings).await?;
+            th.subscribe_vote_events();
+            th.schedule_vote_reminders(sv.shared.reminder_manager().clone(), DEFAULT_REMINDER_POLL_INTERVAL);
+            Ok(())
+        })
+    }),
+    (ApiLayer::name, |sv, cfg, settings| {
+        Box::pin(async move {
+            // The API layer binds its address on startup, so only one worker can run
+            if settings.pool.workers > 1 {
+                return Err("apilayer can only run with one worker".into());
+            }
+
+            sv.start::<ApiLayer>(serde_json::from_value(cfg)?, settings).await?;
+            Ok(())
+        })
+    }),
+];
+
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+/// Per layer settings from the base config
+pub struct LayerSettings {
+    #[serde(flatten)]
+    pub limits: LayerLimits,
+    #[serde(flatten)]
+    pub pool: LayerPoolConfig,
+    /// Messages to dispatch to the layer on cron schedules
+    pub schedules: Vec<ScheduledDispatch>,
+}
+
+/// A running layer with its type erased so layers of different types can be kept together
+pub trait SupervisedLayer: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn cancel(&self);
+    fn stopped(&self) -> BoxFuture<'_, ()>;
+    fn queue_stats(&self) -> LayerQueueStats;
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<L: Layer> SupervisedLayer for LayerPool<L> {
+    fn name(&self) -> &'static str {
+        L::name()
+    }
+
+    fn cancel(&self) {
+        LayerPool::cancel(self)
+    }
+
+    fn stopped(&self) -> BoxFuture<'_, ()> {
+        Box::pin(LayerPool::stopped(self))
+    }
+
+    fn queue_stats(&self) -> LayerQueueStats {
+        LayerPool::queue_stats(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Starts layers and keeps track of them by name so they can be looked up and shut down together
+pub struct LayerSupervisor {
+    pool: sqlx::PgPool,
+    diesel: crate::Db,
+    shared: SharedLayer,
+    layers: HashMap<&'static str, Box<dyn SupervisedLayer>>,
+}
+
+impl LayerSupervisor {
+    pub fn new(pool: sqlx::PgPool, diesel: crate::Db) -> Self {
+        Self {
+            shared: SharedLayer::new(pool.clone(), diesel.clone()),
+            pool,
+            diesel,
+            layers: HashMap::new(),
+        }
+    }
+
+    /// Starts the layers with the given names, in order, using config[name] as the config of each layer
+    /// (falling back to its key in LEGACY_CONFIG_KEYS)
+    ///
+    /// Layers without an entry in settings use the default LayerSettings
+    pub async fn start_from_config(
+        &mut self,
+        names: &[String],
+        settings: &HashMap<String, LayerSettings>,
+        config: &serde_json::Value,
+    ) -> Result<(), crate::Error> {
+        for name in names {
+            let Some((_, start)) = KNOWN_LAYERS.iter().find(|(layer_name, _)| layer_name() == name) else {
+                return Err(format!("Unknown layer: {name}").into());
+            };
+
+            let cfg = config
+                .get(name)
+                .or_else(|| legacy_config(name, config))
+                .cloned()
+                .unwrap_or(serde_json::Value::Null);
+            let layer_settings = settings.get(name).cloned().unwrap_or_default();
+            start(self, cfg, layer_settings)
+                .await
+                .map_err(|e| format!("Failed to start layer {name}: {e}"))?;
+
+            log::info!("Started layer {name}");
+        }
+
+        Ok(())
+    }
+
+    /// Starts a layer's workers and dispatches its default message to each of them as a startup event
+    pub async fn start<L: Layer>(&mut self, config: L::Config, settings: LayerSettings) -> Result<LayerPool<L>, crate::Error>
+    where
+        L::Config: Clone,
+    {
+        if self.layers.contains_key(L::name()) {
+            return Err(format!("Layer {} is already running", L::name()).into());
+        }
+
+        let jobs = settings
+            .schedules
+            .iter()
+            .map(CronJob::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Last runs are keyed on the job name
+        let mut job_names = std::collections::HashSet::new();
+        if let Some(job) = jobs.iter().find(|job| !job_names.insert(job.name.as_str())) {
+            return Err(format!("Duplicate cron job name {} for layer {}", job.name, L::name()).into());
+        }
+
+        let th = LayerPool::new(
+            NewLayerOpts {
+                config,
+                pool: self.pool.clone(),
+                diesel: self.diesel.clone(),
+                limits: settings.limits,
+                // Set per worker by LayerPool::new
+                worker: 0,
+            },
+            settings.pool,
+        );
+
+        if let Err(e) = th.dispatch_startup().await {
+            th.cancel();
+            return Err(e);
+        }
+
+        if let Err(e) = th.schedule_cron(jobs, self.pool.clone()) {
+            th.cancel();
+            return Err(e);
+        }
+
+        self.layers.insert(L::name(), Box::new(th.clone()));
+        Ok(th)
+    }
+
+    /// Returns the running layer of the given type
+    pub fn get<L: Layer>(&self) -> Option<&LayerPool<L>> {
+        self.layers
+            .get(L::name())
+            .and_then(|layer| layer.as_any().downcast_ref::<LayerPool<L>>())
+    }
+
+    /// Returns the running layer with the given name
+    pub fn get_by_name(&self, name: &str) -> Option<&dyn SupervisedLayer> {
+        self.layers.get(name).map(|layer| layer.as_ref())
+    }
+
+    /// Returns the names of all running layers
+    pub fn names(&self) -> Vec<&'static str> {
+        self.layers.keys().copied().collect()
+    }
+
+    /// Cancels every layer and waits up to deadline for all of them to finish cleaning up
+    ///
+    /// Returns the names of the layers that did not stop in time
+    pub async fn shutdown(&mut self, deadline: Duration) -> Vec<&'static str> {
+        for layer in self.layers.values() {
+            layer.cancel();
+        }
+
+        let waits = self.layers.values().map(|layer| async move {
+            match tokio::time::timeout(deadline, layer.stopped()).await {
+                Ok(()) => None,
+                Err(_) => Some(layer.name()),
+            }
+        });
+
+        let timed_out: Vec<&'static str> = futures::future::join_all(waits).await.into_iter().flatten().collect();
+
+        for name in timed_out.iter() {
+            log::error!("Layer {name} did not stop within {deadline:?}");
+        }
+
+        self.layers.clear();
+        timed_out
+    }
+
+    /// Waits for SIGINT or SIGTERM, then shuts down all layers
+    pub async fn run_until_shutdown(mut self, deadline: Duration) -> Result<(), crate::Error> {
+        let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+
+        tokio::select! {
+            res = tokio::signal::ctrl_c() => res?,
+            _ = sigterm.recv() => {},
+        }
+
+        log::info!("Shutting down layers: {:?}", self.names());
+
+        let timed_out = self.shutdown(deadline).await;
+        if !timed_out.is_empty() {
+            return Err(format!("Layers did not stop in time: {}", timed_out.join(", ")).into());
+        }
+
+        Ok(())
+    }
+}
@@ -1,6 +1,17 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, SystemTime};
+
 use mluau_require::rust_embed;
 use mluau_require::Embed;
 
+use crate::config::CONFIG;
+
+/// How often the dev mode watcher checks the Luau directory for changes
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
 #[derive(Embed, Debug)]
 #[folder = "$CARGO_MANIFEST_DIR/src/luau"]
 #[prefix = ""]
@@ -8,4 +19,173 @@ pub struct LuauBase;
 
 pub fn get_luau_vfs() -> mluau_require::vfs::EmbeddedFS<LuauBase> {
     mluau_require::vfs::EmbeddedFS::<LuauBase>::new()
-}
\ No newline at end of file
+}
+
+/// Where layer Luau code is loaded from
+pub enum LuauSource {
+    /// src/luau, embedded at compile time
+    Embedded,
+    /// A directory on disk that is watched for changes (dev mode)
+    Directory(PathBuf),
+}
+
+/// Returns where layer Luau code should be loaded from, set luau_dev_dir in the config to enable dev mode
+pub fn luau_source() -> LuauSource {
+    match &CONFIG.luau_dev_dir {
+        Some(dir) => LuauSource::Directory(PathBuf::from(dir)),
+        None => LuauSource::Embedded,
+    }
+}
+
+/// Returns the top level entries of the Luau directory a layer depends on, given its entrypoint (e.g. ./samplelayer)
+pub fn watched_entries(entrypoint: &str) -> Vec<String> {
+    let layer_dir = entrypoint
+        .trim_start_matches("./")
+        .split('/')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+
+    vec![layer_dir, "common".to_string(), ".luaurc".to_string()]
+}
+
+/// Returns a number that changes whenever a file under one of the given top level entries changes
+///
+/// Always 0 when the Luau code is embedded
+pub fn luau_generation(entries: &[String]) -> u64 {
+    let Some(watcher) = LUAU_WATCHER.as_ref() else {
+        return 0;
+    };
+
+    let generations = watcher.generations.lock().expect("luau watcher poisoned");
+    entries.iter().filter_map(|entry| generations.get(entry)).sum()
+}
+
+/// Dev mode watcher, only created if the Luau code is loaded from disk
+static LUAU_WATCHER: LazyLock<Option<&'static LuauWatcher>> = LazyLock::new(|| {
+    let LuauSource::Directory(root) = luau_source() else {
+        return None;
+    };
+
+    let watcher: &'static LuauWatcher = Box::leak(Box::new(LuauWatcher::new(root)));
+
+    std::thread::Builder::new()
+        .name("LuauWatcher".to_string())
+        .spawn(move || loop {
+            std::thread::sleep(WATCH_INTERVAL);
+            watcher.poll();
+        })
+        .expect("Failed to spawn luau watcher thread");
+
+    Some(watcher)
+});
+
+/// Polls a directory by file modification times, tracking a generation per top level entry
+struct LuauWatcher {
+    root: PathBuf,
+    fingerprints: Mutex<HashMap<String, u64>>,
+    generations: Mutex<HashMap<String, u64>>,
+}
+
+impl LuauWatcher {
+    fn new(root: PathBuf) -> Self {
+        let fingerprints = fingerprint_dir(&root);
+        Self {
+            root,
+            fingerprints: Mutex::new(fingerprints),
+            generations: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn poll(&self) {
+        let new = fingerprint_dir(&self.root);
+        let mut fingerprints = self.fingerprints.lock().expect("luau watcher poisoned");
+        let mut generations = self.generations.lock().expect("luau watcher poisoned");
+
+        for entry in new.keys().chain(fingerprints.keys()) {
+            if new.get(entry) != fingerprints.get(entry) {
+                log::info!("Luau code in {entry} changed");
+                *generations.entry(entry.clone()).or_default() += 1;
+            }
+        }
+
+        *fingerprints = new;
+    }
+}
+
+/// Hashes the path, size and modification time of every file under each top level entry of root
+fn fingerprint_dir(root: &Path) -> HashMap<String, u64> {
+    let mut fingerprints = HashMap::new();
+
+    let Ok(entries) = std::fs::read_dir(root) else {
+        log::error!("Failed to read luau directory {}", root.display());
+        return fingerprints;
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let mut hasher = DefaultHasher::new();
+        hash_tree(&entry.path(), &mut hasher);
+        fingerprints.insert(name, hasher.finish());
+    }
+
+    fingerprints
+}
+
+fn hash_tree(path: &Path, hasher: &mut DefaultHasher) {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return;
+    };
+
+    if metadata.is_dir() {
+        let Ok(entries) = std::fs::read_dir(path) else {
+            return;
+        };
+
+        // read_dir order is not guaranteed
+        let mut paths: Vec<PathBuf> = entries.flatten().map(|e| e.path()).collect();
+        paths.sort();
+
+        for path in paths {
+            hash_tree(&path, hasher);
+        }
+    } else {
+        path.hash(hasher);
+        metadata.len().hash(hasher);
+        metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH).hash(hasher);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_luau_watcher() {
+        let root = std::env::temp_dir().join(format!("apoptosis-luau-watcher-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("samplelayer")).unwrap();
+        std::fs::create_dir_all(root.join("common")).unwrap();
+        std::fs::write(root.join("samplelayer/init.luau"), "return 1").unwrap();
+        std::fs::write(root.join("common/bot.luau"), "return 1").unwrap();
+
+        let watcher = LuauWatcher::new(root.clone());
+        watcher.poll();
+        assert!(watcher.generations.lock().unwrap().is_empty());
+
+        // Size changes are enough to detect a change even if the mtime resolution is coarse
+        std::fs::write(root.join("samplelayer/init.luau"), "return 12").unwrap();
+        watcher.poll();
+
+        let generations = watcher.generations.lock().unwrap().clone();
+        assert_eq!(generations.get("samplelayer"), Some(&1));
+        assert_eq!(generations.get("common"), None);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_watched_entries() {
+        assert_eq!(watched_entries("./samplelayer"), vec!["samplelayer", "common", ".luaurc"]);
+        assert_eq!(watched_entries("./api"), vec!["api", "common", ".luaurc"]);
+    }
+}
@@ -3,7 +3,7 @@ use diesel::prelude::Queryable;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize, Queryable)]
+#[derive(Debug, Serialize, Deserialize, Queryable, sqlx::FromRow, utoipa::ToSchema)]
 #[diesel(check_for_backend(Pg))]
 /// Represents a vote on an entity.
 pub struct EntityVote {
@@ -48,7 +48,7 @@ pub struct VoteInfo {
     pub supports_downvotes: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, utoipa::ToSchema)]
 /// Stores the hours, minutes and seconds until the user can vote again
 pub struct VoteWait {
     /// Hours until the user can vote again
@@ -71,3 +71,361 @@ pub struct UserVote {
     /// The time until the user can vote again
     pub wait: Option<VoteWait>,
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+/// Selects the votes to void. Immutable votes are never voided
+pub enum VoidVotesFilter {
+    /// A single vote given its internal ID
+    Itag {
+        itag: Uuid,
+    },
+    /// All votes made by a user, optionally only those on a single entity
+    User {
+        user_id: String,
+        target_id: Option<String>,
+    },
+    /// All votes on an entity, optionally only those created within a time range
+    Entity {
+        target_id: String,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(tag = "status")]
+/// The outcome of a user attempting to vote on an entity
+pub enum CastVoteOutcome {
+    /// The vote was successful, `votes` is the amount of votes given to the entity
+    Voted {
+        votes: u8,
+    },
+    /// The user has voted recently and must wait before voting again
+    OnCooldown {
+        wait: VoteWait,
+    },
+    /// The entity only allows one vote per user and the user has already voted on it
+    AlreadyVoted,
+    /// The entity does not support voting or the given type of vote
+    NotSupported,
+    /// The entity is banned
+    Banned,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+/// A vote credit tier. Tiers are applied in order of position, each tier paying
+/// `cents` per vote for the next `votes` votes
+pub struct VoteCreditTier {
+    /// The ID of the tier
+    pub id: Uuid,
+    /// The target type the tier applies to
+    pub target_type: String,
+    /// The position of the tier, lower positions are applied first
+    pub position: i32,
+    /// The amount of votes this tier covers. 0 means the tier covers all remaining votes
+    pub votes: i32,
+    /// The amount of cents each vote in this tier is worth
+    pub cents: f64,
+    /// When the tier was created
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+/// The votes and cents a single tier contributed to a credit calculation
+pub struct VoteCreditTierUsage {
+    /// The ID of the tier
+    pub tier_id: Uuid,
+    /// The amount of votes that fell into this tier
+    pub votes: i64,
+    /// The amount of cents earned from this tier
+    pub cents: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+/// The credits an entity has earned from its unredeemed votes
+pub struct VoteCreditBreakdown {
+    /// The amount of unredeemed votes
+    pub votes: i64,
+    /// The total amount of credits (in cents, rounded down) earned
+    pub credits: i32,
+    /// Per-tier breakdown of the credits
+    pub tiers: Vec<VoteCreditTierUsage>,
+}
+
+impl VoteCreditBreakdown {
+    /// Calculates the credits earned from `votes` votes given the tiers
+    ///
+    /// Tiers must be sorted by position. Votes not covered by any tier earn nothing
+    pub fn calculate(tiers: &[VoteCreditTier], votes: i64) -> Self {
+        let mut remaining = votes.max(0);
+        let mut total_cents = 0.0;
+        let mut usages = Vec::new();
+
+        for tier in tiers {
+            if remaining == 0 {
+                break;
+            }
+
+            let tier_votes = if tier.votes == 0 { remaining } else { remaining.min(tier.votes as i64) };
+            let cents = tier_votes as f64 * tier.cents;
+
+            remaining -= tier_votes;
+            total_cents += cents;
+            usages.push(VoteCreditTierUsage {
+                tier_id: tier.id,
+                votes: tier_votes,
+                cents,
+            });
+        }
+
+        Self {
+            votes,
+            credits: total_cents.floor() as i32,
+            tiers: usages,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+/// A redemption of vote credits by an entity
+pub struct VoteCreditRedeemLog {
+    /// The ID of the redemption. Redeemed votes have their credit_redeem set to this
+    pub id: Uuid,
+    /// The ID of the entity
+    pub target_id: String,
+    /// The type of the entity
+    pub target_type: String,
+    /// The amount of credits (in cents) earned by the redemption
+    pub credits: i32,
+    /// The amount of credits that have been paid out so far
+    pub redeemed_credits: i32,
+    /// When the credits were last paid out
+    pub redeemed_at: Option<DateTime<Utc>>,
+    /// When the redemption was made
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+/// A user's opt-in to be reminded when they can vote on an entity again
+pub struct VoteReminder {
+    /// The user to remind
+    pub user_id: String,
+    /// The ID of the entity
+    pub target_id: String,
+    /// The type of the entity
+    pub target_type: String,
+    /// When the user can vote again, None if the user has not voted since the last reminder
+    pub due_at: Option<DateTime<Utc>>,
+    /// When the last reminder was sent
+    pub last_fired_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, utoipa::ToSchema)]
+/// A single entry on a vote leaderboard
+pub struct LeaderboardEntry<S> {
+    /// The position of the entity on the leaderboard, starting at 1
+    pub rank: i64,
+    /// The ID of the entity
+    pub target_id: String,
+    /// The type of the entity
+    pub target_type: String,
+    /// The approximate amount of votes the entity has
+    pub approximate_votes: i32,
+    /// The summary object of the entity, None if the entity could not be fetched
+    pub summary: Option<S>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+/// An entity whose approximate vote count was corrected during reconciliation
+pub struct VoteCountChange {
+    /// The ID of the entity
+    pub target_id: String,
+    /// The type of the entity
+    pub target_type: String,
+    /// The approximate vote count before reconciliation
+    pub old_votes: i32,
+    /// The exact vote count the approximate count was set to
+    pub new_votes: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+/// A periodic vote reset of a target type
+pub struct VoteReset {
+    /// The period being reset (e.g. 2025-01 for a monthly reset)
+    pub period: String,
+    /// The target type being reset
+    pub target_type: String,
+    /// When the reset was first started
+    pub started_at: DateTime<Utc>,
+    /// When the reset completed, None if it is still running or needs to be resumed
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+/// The final vote count of an entity archived by a vote reset
+pub struct VoteResetSnapshot {
+    /// The period that was reset
+    pub period: String,
+    /// The ID of the entity
+    pub target_id: String,
+    /// The type of the entity
+    pub target_type: String,
+    /// The vote count of the entity at the time of the reset
+    pub votes: i32,
+    /// When the snapshot was taken
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+/// Filters for a user's vote history
+pub struct UserVoteHistoryFilter {
+    /// Only return votes on entities of this type
+    pub target_type: Option<String>,
+    /// Whether or not voided votes should be returned
+    #[serde(default)]
+    pub include_void: bool,
+    /// Only return votes created at or after this time
+    pub from: Option<DateTime<Utc>>,
+    /// Only return votes created before this time
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, utoipa::ToSchema)]
+/// A vote made by a user along with the entity it was made on
+pub struct UserVoteHistoryEntry<S> {
+    /// The vote itself
+    pub vote: EntityVote,
+    /// The summary object of the entity voted on, None if the entity could not be fetched
+    pub summary: Option<S>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+/// The size of the buckets in a vote time series
+pub enum VoteBucket {
+    Hour,
+    Day,
+    Week,
+}
+
+impl VoteBucket {
+    /// Returns the date_trunc field for this bucket size
+    pub fn date_trunc_field(&self) -> &'static str {
+        match self {
+            VoteBucket::Hour => "hour",
+            VoteBucket::Day => "day",
+            VoteBucket::Week => "week",
+        }
+    }
+
+    /// Returns the length of a bucket
+    pub fn duration(&self) -> chrono::Duration {
+        match self {
+            VoteBucket::Hour => chrono::Duration::hours(1),
+            VoteBucket::Day => chrono::Duration::days(1),
+            VoteBucket::Week => chrono::Duration::weeks(1),
+        }
+    }
+
+    /// Returns an upper bound on the amount of buckets in the range [from, to), counting the partial buckets at both ends
+    pub fn bucket_count(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> i64 {
+        let len = self.duration().num_seconds();
+        let span = (to - from).num_seconds().max(0);
+        (span + len - 1) / len + 1
+    }
+}
+
+impl std::str::FromStr for VoteBucket {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hour" => Ok(VoteBucket::Hour),
+            "day" => Ok(VoteBucket::Day),
+            "week" => Ok(VoteBucket::Week),
+            _ => Err(format!("Invalid vote bucket: {s}")),
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Errors returned by EntityManager::vote_time_series when the requested range is invalid
+///
+/// These are boxed into crate::Error and can be recovered with downcast_ref
+pub enum VoteTimeSeriesError {
+    /// The start of the range is not before its end
+    InvalidRange,
+    /// The range spans more than the given maximum amount of buckets
+    TooManyBuckets(i64),
+}
+
+impl std::fmt::Display for VoteTimeSeriesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidRange => write!(f, "The start of the range must be before the end"),
+            Self::TooManyBuckets(max) => write!(f, "The range spans too many buckets, at most {max} are allowed. Use a larger bucket or a smaller range"),
+        }
+    }
+}
+
+impl std::error::Error for VoteTimeSeriesError {}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+/// The amount of votes an entity received within a single time bucket
+pub struct VoteTimeSeriesPoint {
+    /// The start of the bucket (UTC)
+    pub bucket: DateTime<Utc>,
+    /// The amount of non-voided upvotes in the bucket
+    pub upvotes: i64,
+    /// The amount of non-voided downvotes in the bucket
+    pub downvotes: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tier(votes: i32, cents: f64) -> VoteCreditTier {
+        VoteCreditTier {
+            id: Uuid::new_v4(),
+            target_type: "bot".to_string(),
+            position: 0,
+            votes,
+            cents,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_credit_tiers() {
+        let tiers = [tier(100, 1.0), tier(0, 0.5)];
+
+        let breakdown = VoteCreditBreakdown::calculate(&tiers, 50);
+        assert_eq!(breakdown.credits, 50);
+        assert_eq!(breakdown.tiers.len(), 1);
+
+        let breakdown = VoteCreditBreakdown::calculate(&tiers, 301);
+        assert_eq!(breakdown.credits, 200);
+        assert_eq!(breakdown.tiers[1].votes, 201);
+
+        // Votes past the last bounded tier earn nothing
+        let breakdown = VoteCreditBreakdown::calculate(&tiers[..1], 150);
+        assert_eq!(breakdown.credits, 100);
+
+        let breakdown = VoteCreditBreakdown::calculate(&tiers, 0);
+        assert_eq!(breakdown.credits, 0);
+        assert!(breakdown.tiers.is_empty());
+    }
+
+    #[test]
+    fn test_bucket_count() {
+        let from = DateTime::parse_from_rfc3339("2025-01-01T00:30:00Z").unwrap().with_timezone(&Utc);
+
+        // 00:00 and 01:00 buckets
+        assert_eq!(VoteBucket::Hour.bucket_count(from, from + chrono::Duration::minutes(45)), 2);
+        assert_eq!(VoteBucket::Day.bucket_count(from, from + chrono::Duration::days(30)), 31);
+        assert!(VoteBucket::Hour.bucket_count(from, from + chrono::Duration::days(365)) > 1000);
+    }
+}